@@ -0,0 +1,197 @@
+use crate::{
+    fetch_topic_detail, Direction, IsolationLevel, PartitionDetailResponse, PartitionOffsets,
+};
+
+const CURSOR_VERSION: u32 = 2;
+
+/// Where to continue reading, handed to clients as an opaque token: the
+/// topic and the position per partition, the direction to read in, the
+/// filter and the isolation level.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    version: u32,
+    topic: String,
+    direction: Direction,
+    offsets: PartitionOffsets,
+    /// The number of partitions the topic had when the cursor was made.
+    partition_count: i32,
+    filter: Option<String>,
+    #[serde(default)]
+    isolation: IsolationLevel,
+}
+
+#[derive(Deserialize)]
+struct CursorVersion {
+    version: u32,
+}
+
+impl Cursor {
+    pub fn new(
+        topic: &str,
+        direction: Direction,
+        offsets: PartitionOffsets,
+        partition_count: i32,
+        filter: Option<String>,
+        isolation: IsolationLevel,
+    ) -> Self {
+        Cursor {
+            version: CURSOR_VERSION,
+            topic: String::from(topic),
+            direction,
+            offsets,
+            partition_count,
+            filter,
+            isolation,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+
+        base64::encode_config(&json, base64::URL_SAFE_NO_PAD)
+    }
+
+    /// Reads a cursor that was made for `topic`.
+    pub fn decode(token: &str, topic: &str) -> Result<Cursor, &'static str> {
+        let json =
+            base64::decode_config(token, base64::URL_SAFE_NO_PAD).map_err(|_| "Invalid cursor")?;
+        let version: CursorVersion = serde_json::from_slice(&json).map_err(|_| "Invalid cursor")?;
+        if version.version != CURSOR_VERSION {
+            return Err("Unsupported cursor version");
+        }
+        let cursor: Cursor = serde_json::from_slice(&json).map_err(|_| "Invalid cursor")?;
+        if cursor.topic != topic {
+            return Err("Cursor is for another topic");
+        }
+
+        Ok(cursor)
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn filter(&self) -> Option<&str> {
+        self.filter.as_deref()
+    }
+
+    pub fn isolation(&self) -> IsolationLevel {
+        self.isolation
+    }
+
+    /// The offsets to continue at, including the partitions that were added
+    /// to the topic since the cursor was made.
+    pub fn offsets_for_topic(&self) -> Result<PartitionOffsets, &'static str> {
+        let topics_detail = fetch_topic_detail(Some(&self.topic))?;
+        let topic_detail = topics_detail.first().ok_or("Can't find topic")?;
+
+        Ok(self.offsets_for_partitions(&topic_detail.partition_details))
+    }
+
+    /// Partitions added after the cursor was made start at their beginning:
+    /// reading forward includes all of their messages, reading backward none,
+    /// as they're all newer than the cursor. Partitions that the cursor left
+    /// out on purpose stay out.
+    fn offsets_for_partitions(&self, partitions: &[PartitionDetailResponse]) -> PartitionOffsets {
+        partitions
+            .iter()
+            .filter_map(|p| match self.offsets.get(&p.id) {
+                Some(offset) => Some((p.id, *offset)),
+                None if p.id >= self.partition_count => Some((p.id, p.lowwatermark_offset)),
+                None => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor() -> Cursor {
+        let offsets = vec![(0, 42), (2, 7)].into_iter().collect();
+
+        Cursor::new(
+            "orders",
+            Direction::Backward,
+            offsets,
+            3,
+            Some(String::from("$.amount > 100")),
+            IsolationLevel::ReadUncommitted,
+        )
+    }
+
+    fn partition(id: i32, lowwatermark_offset: i64) -> PartitionDetailResponse {
+        PartitionDetailResponse {
+            id,
+            highwatermark_offset: 100,
+            lowwatermark_offset,
+            message_count: 100 - lowwatermark_offset,
+        }
+    }
+
+    fn token(json: &str) -> String {
+        base64::encode_config(json, base64::URL_SAFE_NO_PAD)
+    }
+
+    #[test]
+    fn decodes_what_it_encoded() {
+        let cursor = cursor();
+
+        assert_eq!(Cursor::decode(&cursor.encode(), "orders"), Ok(cursor));
+    }
+
+    #[test]
+    fn rejects_cursors_for_another_topic() {
+        assert_eq!(
+            Cursor::decode(&cursor().encode(), "payments"),
+            Err("Cursor is for another topic")
+        );
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let v1 = token(r#"{"version":1,"direction":"forward","offsets":{"0":1},"filter":null}"#);
+        assert_eq!(
+            Cursor::decode(&v1, "orders"),
+            Err("Unsupported cursor version")
+        );
+
+        let mut v3 = serde_json::to_value(cursor()).unwrap();
+        v3["version"] = serde_json::json!(3);
+        let v3 = token(&v3.to_string());
+        assert_eq!(
+            Cursor::decode(&v3, "orders"),
+            Err("Unsupported cursor version")
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_tokens() {
+        assert_eq!(
+            Cursor::decode("not a cursor", "orders"),
+            Err("Invalid cursor")
+        );
+        assert_eq!(
+            Cursor::decode(&token("[]"), "orders"),
+            Err("Invalid cursor")
+        );
+        assert_eq!(
+            Cursor::decode(&token(r#"{"version":2}"#), "orders"),
+            Err("Invalid cursor")
+        );
+    }
+
+    #[test]
+    fn only_adds_partitions_created_after_the_cursor() {
+        let partitions = vec![
+            partition(0, 0),
+            partition(1, 5),
+            partition(2, 0),
+            partition(3, 9),
+        ];
+        let expected: PartitionOffsets = vec![(0, 42), (2, 7), (3, 9)].into_iter().collect();
+
+        assert_eq!(cursor().offsets_for_partitions(&partitions), expected);
+    }
+}
@@ -0,0 +1,364 @@
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use regex::Regex;
+use serde_json::Value;
+
+/// A predicate over the JSON of a decoded message, e.g.
+/// `$.order.id == "123" && $.amount > 100`.
+///
+/// Paths start at `$` and select fields with `.name` or `["name"]` and array
+/// elements with `[0]`. They can be compared with `==`, `!=`, `<`, `<=`, `>`
+/// and `>=` against strings, numbers, `true`, `false` and `null`, or matched
+/// against a regex with `=~ "pattern"`. A path on its own tests whether the
+/// field exists and isn't `null` or `false`. Predicates can be combined with
+/// `&&`, `||`, `!` and parentheses.
+#[derive(Debug)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Exists(Vec<PathSegment>),
+    Compare(Vec<PathSegment>, Operator, Value),
+    Matches(Vec<PathSegment>, Regex),
+}
+
+#[derive(Debug)]
+pub enum PathSegment {
+    Field(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Filter {
+    pub fn parse(expression: &str) -> Result<Filter, String> {
+        let mut parser = Parser {
+            chars: expression.chars().peekable(),
+        };
+        let filter = parser.parse_or()?;
+
+        parser.skip_whitespace();
+        match parser.chars.next() {
+            None => Ok(filter),
+            Some(c) => Err(format!("Unexpected '{}' in filter", c)),
+        }
+    }
+
+    /// Evaluates the filter against a JSON document.
+    pub fn matches(&self, json: &Value) -> bool {
+        match self {
+            Filter::And(left, right) => left.matches(json) && right.matches(json),
+            Filter::Or(left, right) => left.matches(json) || right.matches(json),
+            Filter::Not(filter) => !filter.matches(json),
+            Filter::Exists(path) => match select(json, path) {
+                None | Some(Value::Null) | Some(Value::Bool(false)) => false,
+                Some(_) => true,
+            },
+            Filter::Compare(path, operator, expected) => match select(json, path) {
+                None => *operator == Operator::NotEqual,
+                Some(actual) => compare(actual, *operator, expected),
+            },
+            Filter::Matches(path, regex) => match select(json, path) {
+                Some(Value::String(s)) => regex.is_match(s),
+                Some(Value::Number(n)) => regex.is_match(&n.to_string()),
+                _ => false,
+            },
+        }
+    }
+
+    /// Evaluates the filter against a payload, which never matches when the
+    /// payload isn't valid JSON.
+    pub fn matches_str(&self, json: &str) -> bool {
+        serde_json::from_str(json)
+            .map(|json| self.matches(&json))
+            .unwrap_or(false)
+    }
+}
+
+fn select<'a>(json: &'a Value, path: &[PathSegment]) -> Option<&'a Value> {
+    path.iter().try_fold(json, |value, segment| match segment {
+        PathSegment::Field(name) => value.get(name.as_str()),
+        PathSegment::Index(index) => value.get(*index),
+    })
+}
+
+fn compare(actual: &Value, operator: Operator, expected: &Value) -> bool {
+    let ordering = match (actual, expected) {
+        (Value::Number(a), Value::Number(b)) => a
+            .as_f64()
+            .and_then(|a| b.as_f64().and_then(|b| a.partial_cmp(&b))),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (a, b) if a == b => Some(Ordering::Equal),
+        _ => None,
+    };
+
+    match operator {
+        Operator::Equal => ordering == Some(Ordering::Equal),
+        Operator::NotEqual => ordering != Some(Ordering::Equal),
+        Operator::Less => ordering == Some(Ordering::Less),
+        Operator::LessOrEqual => matches!(ordering, Some(Ordering::Less) | Some(Ordering::Equal)),
+        Operator::Greater => ordering == Some(Ordering::Greater),
+        Operator::GreaterOrEqual => {
+            matches!(ordering, Some(Ordering::Greater) | Some(Ordering::Equal))
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    /// Consumes the token if it's next in the input.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let mut lookahead = self.chars.clone();
+        for expected in token.chars() {
+            if lookahead.next() != Some(expected) {
+                return false;
+            }
+        }
+        self.chars = lookahead;
+
+        true
+    }
+
+    fn parse_or(&mut self) -> Result<Filter, String> {
+        let mut filter = self.parse_and()?;
+        while self.eat("||") {
+            filter = Filter::Or(Box::new(filter), Box::new(self.parse_and()?));
+        }
+
+        Ok(filter)
+    }
+
+    fn parse_and(&mut self) -> Result<Filter, String> {
+        let mut filter = self.parse_unary()?;
+        while self.eat("&&") {
+            filter = Filter::And(Box::new(filter), Box::new(self.parse_unary()?));
+        }
+
+        Ok(filter)
+    }
+
+    fn parse_unary(&mut self) -> Result<Filter, String> {
+        if self.eat("!") {
+            return Ok(Filter::Not(Box::new(self.parse_unary()?)));
+        }
+        if self.eat("(") {
+            let filter = self.parse_or()?;
+            if !self.eat(")") {
+                return Err(String::from("Missing ')' in filter"));
+            }
+            return Ok(filter);
+        }
+
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Filter, String> {
+        let path = self.parse_path()?;
+
+        if self.eat("=~") {
+            let pattern = match self.parse_literal()? {
+                Value::String(pattern) => pattern,
+                _ => return Err(String::from("Expected a string after '=~'")),
+            };
+            let regex = Regex::new(&pattern).map_err(|e| format!("Invalid regex: {}", e))?;
+            return Ok(Filter::Matches(path, regex));
+        }
+
+        let operator = if self.eat("==") {
+            Operator::Equal
+        } else if self.eat("!=") {
+            Operator::NotEqual
+        } else if self.eat("<=") {
+            Operator::LessOrEqual
+        } else if self.eat(">=") {
+            Operator::GreaterOrEqual
+        } else if self.eat("<") {
+            Operator::Less
+        } else if self.eat(">") {
+            Operator::Greater
+        } else {
+            return Ok(Filter::Exists(path));
+        };
+
+        Ok(Filter::Compare(path, operator, self.parse_literal()?))
+    }
+
+    fn parse_path(&mut self) -> Result<Vec<PathSegment>, String> {
+        if !self.eat("$") {
+            return Err(String::from("Expected a path starting with '$' in filter"));
+        }
+
+        let mut path = vec![];
+        loop {
+            match self.chars.peek() {
+                Some('.') => {
+                    self.chars.next();
+                    let name = self.take_while(|c| c.is_alphanumeric() || c == '_' || c == '-');
+                    if name.is_empty() {
+                        return Err(String::from("Expected a field name after '.' in filter"));
+                    }
+                    path.push(PathSegment::Field(name));
+                }
+                Some('[') => {
+                    self.chars.next();
+                    let segment = match self.parse_literal()? {
+                        Value::String(name) => PathSegment::Field(name),
+                        Value::Number(ref n) if n.is_u64() => {
+                            PathSegment::Index(n.as_u64().unwrap_or_default() as usize)
+                        }
+                        _ => return Err(String::from("Expected a field name or index in '[]'")),
+                    };
+                    if !self.eat("]") {
+                        return Err(String::from("Missing ']' in filter"));
+                    }
+                    path.push(segment);
+                }
+                _ => return Ok(path),
+            }
+        }
+    }
+
+    fn parse_literal(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+
+        match self.chars.peek() {
+            Some('"') | Some('\'') => {
+                let quote = self.chars.next().unwrap_or('"');
+                let mut literal = String::new();
+                loop {
+                    match self.chars.next() {
+                        None => return Err(String::from("Unterminated string in filter")),
+                        // Only quotes and backslashes are escaped, other
+                        // backslashes are kept for regexes like "\d+".
+                        Some('\\') => match self.chars.next() {
+                            Some(c) if c == '"' || c == '\'' || c == '\\' => literal.push(c),
+                            Some(c) => {
+                                literal.push('\\');
+                                literal.push(c);
+                            }
+                            None => return Err(String::from("Unterminated string in filter")),
+                        },
+                        Some(c) if c == quote => return Ok(Value::String(literal)),
+                        Some(c) => literal.push(c),
+                    }
+                }
+            }
+            _ => {
+                let literal = self.take_while(|c| c.is_alphanumeric() || "-+.".contains(c));
+                match literal.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "null" => Ok(Value::Null),
+                    number => serde_json::from_str::<serde_json::Number>(number)
+                        .map(Value::Number)
+                        .map_err(|_| format!("Invalid value '{}' in filter", number)),
+                }
+            }
+        }
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, predicate: F) -> String {
+        let mut taken = String::new();
+        while let Some(c) = self.chars.peek() {
+            if !predicate(*c) {
+                break;
+            }
+            taken.push(*c);
+            self.chars.next();
+        }
+
+        taken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn matches(expression: &str, json: Value) -> bool {
+        Filter::parse(expression)
+            .expect("filter should parse")
+            .matches(&json)
+    }
+
+    #[test]
+    fn keeps_backslashes_in_regexes() {
+        assert!(matches(r#"$.id =~ "^\d+$""#, json!({"id": "123"})));
+        assert!(!matches(r#"$.id =~ "^\d+$""#, json!({"id": "ddd"})));
+        assert!(matches(r#"$.host =~ "a\.b""#, json!({"host": "a.b"})));
+        assert!(!matches(r#"$.host =~ "a\.b""#, json!({"host": "axb"})));
+    }
+
+    #[test]
+    fn unescapes_quotes_and_backslashes() {
+        assert!(matches(
+            r#"$.name == "say \"hi\"""#,
+            json!({"name": "say \"hi\""})
+        ));
+        assert!(matches(r#"$.name == 'it\'s'"#, json!({"name": "it's"})));
+        assert!(matches(r#"$.path == "a\\b""#, json!({"path": "a\\b"})));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let json = json!({"a": 1, "b": 2, "c": 3});
+        assert!(matches("$.a == 1 || $.b == 0 && $.c == 0", json.clone()));
+        assert!(!matches("($.a == 1 || $.b == 0) && $.c == 0", json.clone()));
+        assert!(matches("!$.missing && $.a == 1", json));
+    }
+
+    #[test]
+    fn tells_less_or_equal_from_less() {
+        let json = json!({"amount": 100});
+        assert!(matches("$.amount <= 100", json.clone()));
+        assert!(!matches("$.amount < 100", json.clone()));
+        assert!(matches("$.amount >= 100", json.clone()));
+        assert!(!matches("$.amount > 100", json));
+    }
+
+    #[test]
+    fn missing_paths_are_not_equal() {
+        let json = json!({"order": {"id": "123"}});
+        assert!(matches(r#"$.order.status != "done""#, json.clone()));
+        assert!(!matches(r#"$.order.status == "done""#, json.clone()));
+        assert!(!matches("$.order.status", json));
+    }
+
+    #[test]
+    fn selects_fields_and_indexes() {
+        let json = json!({"items": [{"sku": "a"}, {"sku": "b"}], "odd key": true});
+        assert!(matches(r#"$.items[1].sku == "b""#, json.clone()));
+        assert!(matches(r#"$["odd key"]"#, json));
+    }
+
+    #[test]
+    fn rejects_invalid_filters() {
+        assert!(Filter::parse("$.a ==").is_err());
+        assert!(Filter::parse("($.a == 1").is_err());
+        assert!(Filter::parse(r#"$.a == "open"#).is_err());
+        assert!(Filter::parse("a == 1").is_err());
+        assert!(Filter::parse("$.a == 1 junk").is_err());
+    }
+}
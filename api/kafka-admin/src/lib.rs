@@ -1,28 +1,55 @@
 #[macro_use]
 extern crate serde_derive;
-#[macro_use]
-extern crate log;
 extern crate rdkafka;
 
-use std::collections::HashMap;
+mod cursor;
+mod filter;
+mod page;
+mod partitioner;
+mod produce;
+mod scan;
+mod search;
+mod table;
+mod tail;
+mod transactions;
+
+pub use cursor::Cursor;
+pub use filter::Filter;
+pub use paging::{Direction, PageCursor, PageLimits, PartitionOffsets};
+pub use partitioner::Partitioner;
+pub use produce::{
+    produce, produce_batch, Acks, BatchFailure, BatchProduceResponse, BatchRecordResult,
+    ProduceError, ProduceErrorKind, ProduceHeader, ProduceOptions, ProduceRecord, ProduceResponse,
+};
+pub use search::{
+    search, search_key, KeyMessagesResponse, PartitionProgress, SearchEvent, SearchPattern,
+    SearchRequest,
+};
+pub use table::{decode_table_key, materialize_table, TableRequest, TableResponse, TableRow};
+pub use tail::{tail, tail_topics, SeekPosition, TailCommand, TailEvent};
+pub use transactions::{IsolationLevel, TransactionMarker, TransactionStatus};
+
+use std::collections::{HashMap, HashSet};
+use std::slice;
 use std::time::Duration;
 use std::{env, thread};
 
 use futures::*;
 use rdkafka::admin::TopicReplication::Fixed;
 use rdkafka::admin::{AdminClient, AdminOptions, NewTopic};
-use rdkafka::client::ClientContext;
 use rdkafka::client::DefaultClientContext;
-use rdkafka::config::{ClientConfig, RDKafkaLogLevel};
-use rdkafka::consumer::stream_consumer::StreamConsumer;
-use rdkafka::consumer::{
-    BaseConsumer, CommitMode, Consumer, ConsumerContext, DefaultConsumerContext, Rebalance,
-};
-use rdkafka::message::Message;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, Consumer, DefaultConsumerContext};
+use rdkafka::message::{Headers, Message, Timestamp};
 use rdkafka::topic_partition_list::Offset::Offset;
 use rdkafka::TopicPartitionList;
 
 use backoff::{ExponentialBackoff, Operation};
+use chrono::{SecondsFormat, TimeZone, Utc};
+use page::{Admission, Page};
+use payload_decoder::{decode_payload, DecodedPayload, RawBytes};
+use scan::{create_assign_consumer, scan_isolated, PartitionRange, ScanEvent};
+use transactions::annotate_transactions;
 
 fn create_config() -> ClientConfig {
     let mut config = ClientConfig::new();
@@ -42,7 +69,14 @@ pub fn get_broker_list() -> String {
     broker_list
 }
 
-pub type PartitionOffsets = HashMap<i32, i64>;
+/// The maximum number of messages a filtered request reads before giving up
+/// on filling a page, configured with `FILTER_SCAN_BUDGET`.
+pub fn get_filter_scan_budget() -> usize {
+    env::var("FILTER_SCAN_BUDGET")
+        .ok()
+        .and_then(|budget| budget.parse().ok())
+        .unwrap_or(10_000)
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TopicDetailResponse {
@@ -59,18 +93,140 @@ pub struct PartitionDetailResponse {
     pub message_count: i64,
 }
 
+/// How to read a page of messages.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadOptions {
+    pub direction: Direction,
+    pub page_limits: PageLimits,
+    pub isolation: IsolationLevel,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MessagesResponse {
     messages: Vec<MessageResponse>,
     offsets: PartitionOffsets,
+    /// Continues in the direction that was read.
+    next: PageCursor,
+    /// Goes back the other way, to the messages before this page.
+    previous: PageCursor,
+    scanned_messages: usize,
+    scan_budget_exhausted: bool,
+    /// The offsets a timestamp resolved to, when reading from a timestamp.
+    resolved_offsets: Option<PartitionOffsets>,
+    /// Opaque versions of `next` and `previous`, which also keep the filter.
+    next_cursor: Option<String>,
+    previous_cursor: Option<String>,
+    isolation: IsolationLevel,
+    /// Transaction markers between the messages, only when reading
+    /// uncommitted.
+    markers: Vec<TransactionMarker>,
+    /// The number of partitions the topic had when it was read.
+    #[serde(skip)]
+    partition_count: i32,
+}
+
+impl MessagesResponse {
+    /// Adds the opaque cursors for the next and previous page.
+    pub fn with_cursors(mut self, topic: &str, filter: Option<&str>) -> Self {
+        let filter = filter.map(String::from);
+        let next = Cursor::new(
+            topic,
+            self.next.direction,
+            self.next.offsets.clone(),
+            self.partition_count,
+            filter.clone(),
+            self.isolation,
+        );
+        let previous = Cursor::new(
+            topic,
+            self.previous.direction,
+            self.previous.offsets.clone(),
+            self.partition_count,
+            filter,
+            self.isolation,
+        );
+        self.next_cursor = Some(next.encode());
+        self.previous_cursor = Some(previous.encode());
+
+        self
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MessageResponse {
-    json: String,
+    key: Option<RawBytes>,
+    #[serde(flatten)]
+    payload: DecodedPayload,
+    headers: Vec<HeaderResponse>,
     offset: i64,
     partition: i32,
-    timestamp: i64,
+    timestamp: Option<i64>,
+    timestamp_type: TimestampType,
+    timestamp_iso: Option<String>,
+    /// Only set when reading uncommitted.
+    transaction_status: Option<TransactionStatus>,
+}
+
+impl MessageResponse {
+    fn from_message<M: Message>(topic: &str, message: &M) -> Self {
+        let (timestamp, timestamp_type, timestamp_iso) = message_timestamp(message);
+
+        MessageResponse {
+            key: message.key().map(RawBytes::new),
+            payload: decode_payload(topic, message.payload().unwrap_or(&[])),
+            headers: message_headers(message),
+            offset: message.offset(),
+            partition: message.partition(),
+            timestamp,
+            timestamp_type,
+            timestamp_iso,
+            transaction_status: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampType {
+    CreateTime,
+    LogAppendTime,
+    NotAvailable,
+}
+
+/// Returns the timestamp of a message in millis, what kind of timestamp it is
+/// and its ISO-8601 representation.
+fn message_timestamp<M: Message>(message: &M) -> (Option<i64>, TimestampType, Option<String>) {
+    let (millis, timestamp_type) = match message.timestamp() {
+        Timestamp::CreateTime(millis) => (Some(millis), TimestampType::CreateTime),
+        Timestamp::LogAppendTime(millis) => (Some(millis), TimestampType::LogAppendTime),
+        Timestamp::NotAvailable => (None, TimestampType::NotAvailable),
+    };
+
+    let iso = millis
+        .and_then(|millis| Utc.timestamp_millis_opt(millis).single())
+        .map(|time| time.to_rfc3339_opts(SecondsFormat::Millis, true));
+
+    (millis, timestamp_type, iso)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HeaderResponse {
+    name: String,
+    value: RawBytes,
+}
+
+/// Returns the headers of a message in the order they were produced.
+fn message_headers<M: Message>(message: &M) -> Vec<HeaderResponse> {
+    match message.headers() {
+        None => vec![],
+        Some(headers) => (0..headers.count())
+            .filter_map(|i| headers.get(i))
+            .map(|(name, value)| HeaderResponse {
+                name: String::from(name),
+                value: RawBytes::new(value),
+            })
+            .collect(),
+    }
 }
 
 pub fn fetch_topic_detail(topic: Option<&str>) -> Result<Vec<TopicDetailResponse>, &'static str> {
@@ -115,118 +271,474 @@ pub fn fetch_topic_detail(topic: Option<&str>) -> Result<Vec<TopicDetailResponse
     Ok(topics)
 }
 
-struct CustomContext;
-
-impl ClientContext for CustomContext {}
+/// Reads a page of messages from the given offsets, see [`Direction`].
+/// Without a filter partitions are read up to the per partition limit. With
+/// a filter, partitions are read until the page is full, the end (or start)
+/// of the partitions or the scan budget was reached; the offsets tell how far
+/// each partition was scanned.
+pub fn consume(
+    topic: &str,
+    offsets: &PartitionOffsets,
+    options: &ReadOptions,
+    filter: Option<&Filter>,
+) -> Result<MessagesResponse, &'static str> {
+    let mut response = match options.direction {
+        Direction::Forward => consume_forward(topic, offsets, options, filter),
+        Direction::Backward => consume_backward(topic, offsets, options, filter),
+    }?;
 
-impl ConsumerContext for CustomContext {
-    fn pre_rebalance(&self, rebalance: &Rebalance) {
-        info!("Pre rebalance {:?}", rebalance);
+    if options.isolation == IsolationLevel::ReadUncommitted {
+        response.markers = annotate_transactions(topic, &mut response.messages)?;
     }
 
-    fn post_rebalance(&self, rebalance: &Rebalance) {
-        info!("Post rebalance {:?}", rebalance);
-    }
+    Ok(response)
 }
 
-type KafkaConsumer = StreamConsumer<CustomContext>;
-pub fn consume(
-    brokers: &str,
-    group_id: &str,
+/// Reads the messages from the given offsets. Partitions are read in chunks
+/// of the per partition limit until the page is full, the end of the
+/// partitions or the scan budget was reached.
+fn consume_forward(
     topic: &str,
     offsets: &PartitionOffsets,
+    options: &ReadOptions,
+    filter: Option<&Filter>,
 ) -> Result<MessagesResponse, &'static str> {
+    let page_limits = &options.page_limits;
     let topics_detail = fetch_topic_detail(Some(topic))?;
-    let topic_detail = topics_detail.first().expect("topic not found");
+    let topic_detail = topics_detail.first().ok_or("Can't find topic")?;
+
+    // The start of the part of each partition that's left to read.
+    let mut positions: PartitionOffsets = HashMap::new();
+    let mut high_offsets: PartitionOffsets = HashMap::new();
+    for (partition, offset) in offsets {
+        let partition_detail = topic_detail
+            .partition_details
+            .iter()
+            .find(|p| p.id == *partition)
+            .ok_or("Partition not found")?;
+
+        positions.insert(
+            *partition,
+            partition_detail
+                .lowwatermark_offset
+                .max(*offset)
+                .min(partition_detail.highwatermark_offset),
+        );
+        high_offsets.insert(*partition, partition_detail.highwatermark_offset);
+    }
+    let start_offsets = positions.clone();
+
+    let scan_budget = get_filter_scan_budget();
+    let mut scanned_messages = 0;
+    let mut scan_budget_exhausted = false;
+    let mut page = Page::new(*page_limits);
+    // Partitions that are full, or have no more messages to read yet.
+    let mut done_partitions: HashSet<i32> = HashSet::new();
+    let chunk_size = page_limits.per_partition_limit as i64;
+
+    loop {
+        let ranges: Vec<PartitionRange> = positions
+            .iter()
+            .filter(|(partition, position)| {
+                !done_partitions.contains(*partition) && **position < high_offsets[*partition]
+            })
+            .map(|(partition, position)| PartitionRange {
+                partition: *partition,
+                start: *position,
+                end: (position + chunk_size).min(high_offsets[partition]),
+            })
+            .collect();
+        if ranges.is_empty() {
+            break;
+        }
+        let range_ends: PartitionOffsets = ranges
+            .iter()
+            .map(|range| (range.partition, range.end))
+            .collect();
+
+        let mut page_full = false;
+        scan_isolated(topic, &ranges, options.isolation, |event| {
+            match event {
+                ScanEvent::Message(m) => {
+                    let (partition, offset) = (m.partition(), m.offset());
+                    if done_partitions.contains(&partition) {
+                        return true;
+                    }
+                    let message = MessageResponse::from_message(topic, m);
+                    scanned_messages += 1;
+
+                    let matched = match filter {
+                        None => true,
+                        Some(filter) => filter.matches_str(&message.payload.json),
+                    };
+                    if matched {
+                        // A message that doesn't fit becomes the start of its
+                        // partition for the next page.
+                        match page.add(message) {
+                            Admission::Added => {}
+                            Admission::PartitionFull => {
+                                done_partitions.insert(partition);
+                                return true;
+                            }
+                            Admission::PageFull => {
+                                page_full = true;
+                                return false;
+                            }
+                        }
+                    }
+                    positions.insert(partition, offset + 1);
+                }
+                ScanEvent::PartitionEnd {
+                    partition,
+                    reached_end,
+                } => {
+                    // Reading committed messages, a partition runs out at its
+                    // first open transaction, which has to be read again once
+                    // it's committed.
+                    if reached_end {
+                        if !done_partitions.contains(&partition) {
+                            positions.insert(partition, range_ends[&partition]);
+                        }
+                    } else {
+                        done_partitions.insert(partition);
+                    }
+                }
+            }
+            true
+        })?;
 
-    let mut limits: PartitionOffsets = HashMap::new();
-    let mut messages_received: PartitionOffsets = HashMap::new();
+        if filter.is_none() || page_full || page.is_full() {
+            break;
+        }
+        if scanned_messages >= scan_budget {
+            scan_budget_exhausted = positions.iter().any(|(partition, position)| {
+                !done_partitions.contains(partition) && *position < high_offsets[partition]
+            });
+            break;
+        }
+    }
 
-    let context = CustomContext;
-    let mut tpl = TopicPartitionList::new();
+    Ok(MessagesResponse {
+        messages: page.into_messages(),
+        offsets: positions.clone(),
+        next: PageCursor {
+            direction: Direction::Forward,
+            offsets: positions,
+        },
+        previous: PageCursor {
+            direction: Direction::Backward,
+            offsets: start_offsets,
+        },
+        scanned_messages,
+        scan_budget_exhausted,
+        resolved_offsets: None,
+        next_cursor: None,
+        previous_cursor: None,
+        isolation: options.isolation,
+        markers: vec![],
+        partition_count: topic_detail.partition_details.len() as i32,
+    })
+}
+
+/// Reads the messages right before the given offsets. Partitions are read
+/// back in chunks of the per partition limit until the page is full, the
+/// start of the partitions or the scan budget was reached.
+fn consume_backward(
+    topic: &str,
+    offsets: &PartitionOffsets,
+    options: &ReadOptions,
+    filter: Option<&Filter>,
+) -> Result<MessagesResponse, &'static str> {
+    let page_limits = &options.page_limits;
+    let topics_detail = fetch_topic_detail(Some(topic))?;
+    let topic_detail = topics_detail.first().ok_or("Can't find topic")?;
 
-    offsets.iter().for_each(|(partition, offset)| {
-        let partition_detail: &PartitionDetailResponse = topic_detail
+    // The end of the part of each partition that's left to read, exclusive.
+    let mut positions: PartitionOffsets = HashMap::new();
+    let mut low_offsets: PartitionOffsets = HashMap::new();
+    for (partition, offset) in offsets {
+        let partition_detail = topic_detail
             .partition_details
-            .get(*partition as usize)
-            .expect("partition not found");
-        let max = (partition_detail.highwatermark_offset - 1).min(*offset + 20);
-        let min = partition_detail.lowwatermark_offset.max(*offset);
-
-        if min >= max {
-            messages_received.insert(*partition, partition_detail.highwatermark_offset);
-            return;
+            .iter()
+            .find(|p| p.id == *partition)
+            .ok_or("Partition not found")?;
+
+        positions.insert(
+            *partition,
+            (*offset).min(partition_detail.highwatermark_offset),
+        );
+        low_offsets.insert(*partition, partition_detail.lowwatermark_offset);
+    }
+    let end_offsets = positions.clone();
+
+    let scan_budget = get_filter_scan_budget();
+    let mut scanned_messages = 0;
+    let mut scan_budget_exhausted = false;
+    let mut page = Page::new(*page_limits);
+    let mut full_partitions: HashSet<i32> = HashSet::new();
+    let chunk_size = page_limits.per_partition_limit as i64;
+
+    loop {
+        let ranges: Vec<PartitionRange> = positions
+            .iter()
+            .filter(|(partition, position)| {
+                !full_partitions.contains(*partition) && low_offsets[*partition] < **position
+            })
+            .map(|(partition, position)| PartitionRange {
+                partition: *partition,
+                start: (position - chunk_size).max(low_offsets[partition]),
+                end: *position,
+            })
+            .collect();
+        if ranges.is_empty() {
+            break;
         }
 
-        limits.insert(*partition, max);
-        messages_received.insert(*partition, min);
-
-        tpl.add_partition_offset(topic, *partition, Offset(*offset));
-    });
-
-    let consumer: KafkaConsumer = ClientConfig::new()
-        .set("group.id", group_id)
-        .set("bootstrap.servers", brokers)
-        .set("enable.partition.eof", "false")
-        .set("session.timeout.ms", "6000")
-        .set("enable.auto.commit", "true")
-        .set_log_level(RDKafkaLogLevel::Debug)
-        .create_with_context(context)
-        .expect("Consumer creation failed");
-
-    consumer
-        .assign(&tpl)
-        .map_err(|_| "Can't subscribe to specified partitions")?;
-
-    let message_stream = consumer.start();
-    let mut messages = vec![];
-    for message in message_stream.wait() {
-        match message {
-            Err(_) => eprintln!("Error while reading from stream."),
-            Ok(Err(e)) => eprintln!("Kafka error: {}", e),
-            Ok(Ok(m)) => {
-                let payload = match m.payload_view::<str>() {
-                    None => "",
-                    Some(Ok(s)) => s,
-                    Some(Err(e)) => {
-                        eprintln!("Error while deserializing message payload: {:?}", e);
-                        "{\"error\": \"Can't deserialize message payload\" }"
-                    }
+        let mut matches: HashMap<i32, Vec<MessageResponse>> = HashMap::new();
+        scan_isolated(topic, &ranges, options.isolation, |event| {
+            if let ScanEvent::Message(m) = event {
+                let message = MessageResponse::from_message(topic, m);
+                scanned_messages += 1;
+
+                let matched = match filter {
+                    None => true,
+                    Some(filter) => filter.matches_str(&message.payload.json),
                 };
-                messages_received.insert(m.partition(), m.offset());
-                consumer.commit_message(&m, CommitMode::Async).unwrap();
+                if matched {
+                    matches.entry(message.partition).or_default().push(message);
+                }
+            }
+            true
+        })?;
 
-                let finished = limits.iter().all(|(k, v)| {
-                    let current_offset = messages_received.get(k).unwrap();
+        for range in &ranges {
+            positions.insert(range.partition, range.start);
+        }
 
-                    v <= current_offset
-                });
+        // Newest first and taking turns between partitions, so a full page
+        // keeps the messages closest to where reading started. A message that
+        // doesn't fit becomes the end of its partition for the next page.
+        let mut matches: Vec<(i32, Vec<MessageResponse>)> = matches.into_iter().collect();
+        matches.sort_by_key(|(partition, _)| *partition);
+        let mut newest_first: Vec<_> = matches
+            .into_iter()
+            .map(|(_, messages)| messages.into_iter().rev())
+            .collect();
+        let mut page_full = false;
+        loop {
+            let mut taken = false;
+            for messages in newest_first.iter_mut() {
+                let message = match messages.next() {
+                    Some(message) => message,
+                    None => continue,
+                };
+                taken = true;
 
-                if finished {
-                    consumer.stop();
+                let (partition, offset) = (message.partition, message.offset);
+                if full_partitions.contains(&partition) {
+                    continue;
                 }
-
-                messages.push(MessageResponse {
-                    json: String::from(payload),
-                    offset: m.offset(),
-                    partition: m.partition(),
-                    timestamp: m
-                        .timestamp()
-                        .to_millis()
-                        .ok_or("No timestamp for message")?,
-                })
+                let admission = if page_full {
+                    Admission::PageFull
+                } else {
+                    page.add(message)
+                };
+                match admission {
+                    Admission::Added => {}
+                    Admission::PartitionFull | Admission::PageFull => {
+                        page_full = page_full || matches!(admission, Admission::PageFull);
+                        full_partitions.insert(partition);
+                        positions.insert(partition, offset + 1);
+                    }
+                }
+            }
+            if !taken {
+                break;
             }
-        };
+        }
+
+        if filter.is_none() || page_full || page.is_full() {
+            break;
+        }
+        if scanned_messages >= scan_budget {
+            scan_budget_exhausted = positions.iter().any(|(partition, position)| {
+                !full_partitions.contains(partition) && low_offsets[partition] < *position
+            });
+            break;
+        }
     }
-    println!("hello there");
-    eprintln!("messages_received = {:#?}", messages_received);
 
     Ok(MessagesResponse {
-        messages,
-        offsets: messages_received,
+        messages: page.into_messages(),
+        offsets: positions.clone(),
+        next: PageCursor {
+            direction: Direction::Backward,
+            offsets: positions,
+        },
+        previous: PageCursor {
+            direction: Direction::Forward,
+            offsets: end_offsets,
+        },
+        scanned_messages,
+        scan_budget_exhausted,
+        resolved_offsets: None,
+        next_cursor: None,
+        previous_cursor: None,
+        isolation: options.isolation,
+        markers: vec![],
+        partition_count: topic_detail.partition_details.len() as i32,
     })
 }
 
+/// Returns the high watermark of every partition, to start reading backward
+/// from the latest messages.
+pub fn latest_offsets(topic: &str) -> Result<PartitionOffsets, &'static str> {
+    let topics_detail = fetch_topic_detail(Some(topic))?;
+    let topic_detail = topics_detail.first().ok_or("Can't find topic")?;
+
+    Ok(topic_detail
+        .partition_details
+        .iter()
+        .map(|p| (p.id, p.highwatermark_offset))
+        .collect())
+}
+
+/// Like [`consume`], but starts every partition at its first message at or
+/// after `timestamp` (in millis), using Kafka's offsets for times lookup.
+/// Reading backward returns the messages before that point in time.
+pub fn consume_from_timestamp(
+    topic: &str,
+    timestamp: i64,
+    options: &ReadOptions,
+    filter: Option<&Filter>,
+) -> Result<MessagesResponse, &'static str> {
+    let topics_detail = fetch_topic_detail(Some(topic))?;
+    let topic_detail = topics_detail.first().ok_or("Can't find topic")?;
+    let offsets = offsets_for_timestamp(topic, &topic_detail.partition_details, timestamp)?;
+
+    let mut response = consume(topic, &offsets, options, filter)?;
+    response.resolved_offsets = Some(offsets);
+
+    Ok(response)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MessageNotFoundResponse {
+    error: String,
+    /// Not set when the partition doesn't exist.
+    lowwatermark_offset: Option<i64>,
+    highwatermark_offset: Option<i64>,
+}
+
+/// Fetches the single message at an offset. When there's no message at that
+/// offset, because it's outside the watermarks, was removed by compaction or
+/// isn't visible with the isolation level, the watermarks of the partition
+/// are returned instead.
+pub fn fetch_message(
+    topic: &str,
+    partition: i32,
+    offset: i64,
+    isolation: IsolationLevel,
+) -> Result<Result<MessageResponse, MessageNotFoundResponse>, &'static str> {
+    let topics_detail = fetch_topic_detail(Some(topic))?;
+    let partition_detail = topics_detail
+        .first()
+        .and_then(|t| t.partition_details.iter().find(|p| p.id == partition));
+    let (low, high) = match partition_detail {
+        Some(p) => (p.lowwatermark_offset, p.highwatermark_offset),
+        None => {
+            return Ok(Err(MessageNotFoundResponse {
+                error: format!("Partition {} doesn't exist", partition),
+                lowwatermark_offset: None,
+                highwatermark_offset: None,
+            }))
+        }
+    };
+
+    let not_found = |error: String| MessageNotFoundResponse {
+        error,
+        lowwatermark_offset: Some(low),
+        highwatermark_offset: Some(high),
+    };
+
+    if offset < low {
+        return Ok(Err(not_found(format!(
+            "Offset {} is below the low watermark {}",
+            offset, low
+        ))));
+    }
+    if offset >= high {
+        return Ok(Err(not_found(format!(
+            "Offset {} is beyond the high watermark {}",
+            offset, high
+        ))));
+    }
+
+    let range = PartitionRange {
+        partition,
+        start: offset,
+        end: offset + 1,
+    };
+    let mut message = None;
+    scan_isolated(topic, &[range], isolation, |event| {
+        if let ScanEvent::Message(m) = event {
+            message = Some(MessageResponse::from_message(topic, m));
+        }
+        true
+    })?;
+
+    let mut message = match message {
+        Some(message) => message,
+        None => {
+            return Ok(Err(not_found(format!(
+                "No message found at offset {}",
+                offset
+            ))))
+        }
+    };
+    if isolation == IsolationLevel::ReadUncommitted {
+        annotate_transactions(topic, slice::from_mut(&mut message))?;
+    }
+
+    Ok(Ok(message))
+}
+
+/// Looks up the first offset per partition with a timestamp at or after
+/// `timestamp` (in millis). Partitions without such a message get their high
+/// watermark.
+pub fn offsets_for_timestamp(
+    topic: &str,
+    partitions: &[PartitionDetailResponse],
+    timestamp: i64,
+) -> Result<PartitionOffsets, &'static str> {
+    let consumer = create_assign_consumer()?;
+
+    let mut tpl = TopicPartitionList::new();
+    for partition in partitions {
+        tpl.add_partition_offset(topic, partition.id, Offset(timestamp));
+    }
+
+    let resolved = consumer
+        .offsets_for_times(tpl, Duration::from_secs(3))
+        .map_err(|_| "Failed to look up offsets for timestamp")?;
+
+    let offsets = partitions
+        .iter()
+        .map(|partition| {
+            let offset = match resolved
+                .find_partition(topic, partition.id)
+                .map(|elem| elem.offset())
+            {
+                Some(Offset(offset)) if offset >= 0 => offset,
+                _ => partition.highwatermark_offset,
+            };
+            (partition.id, offset)
+        })
+        .collect();
+
+    Ok(offsets)
+}
+
 fn verify_delete(topic: &str) {
     let consumer: BaseConsumer<DefaultConsumerContext> =
         create_config().create().expect("consumer creation failed");
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use paging::PageLimits;
+use payload_decoder::get_max_listed_payload_bytes;
+
+use crate::MessageResponse;
+
+pub enum Admission {
+    Added,
+    /// The partition has reached its limit, the message belongs on the next
+    /// page.
+    PartitionFull,
+    /// The page has reached its limits, the message belongs on the next page.
+    PageFull,
+}
+
+/// Collects the messages of a page while keeping it within its limits.
+pub struct Page {
+    limits: PageLimits,
+    max_payload_bytes: usize,
+    messages: Vec<MessageResponse>,
+    bytes: usize,
+    per_partition: HashMap<i32, usize>,
+}
+
+impl Page {
+    pub fn new(limits: PageLimits) -> Self {
+        Page {
+            limits,
+            max_payload_bytes: get_max_listed_payload_bytes(),
+            messages: vec![],
+            bytes: 0,
+            per_partition: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, mut message: MessageResponse) -> Admission {
+        let size = message.payload.size;
+        if self.is_full()
+            || (!self.messages.is_empty() && self.bytes + size > self.limits.max_bytes)
+        {
+            return Admission::PageFull;
+        }
+
+        let count = self.per_partition.entry(message.partition).or_insert(0);
+        if *count >= self.limits.per_partition_limit {
+            return Admission::PartitionFull;
+        }
+        *count += 1;
+        self.bytes += size;
+
+        message.payload.truncate(self.max_payload_bytes);
+        self.messages.push(message);
+
+        Admission::Added
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.messages.len() >= self.limits.limit || self.bytes >= self.limits.max_bytes
+    }
+
+    /// The messages in offset order per partition.
+    pub fn into_messages(mut self) -> Vec<MessageResponse> {
+        self.messages.sort_by_key(|m| (m.partition, m.offset));
+
+        self.messages
+    }
+}
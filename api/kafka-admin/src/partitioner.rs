@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+/// The murmur2 hash as implemented by the Java client, which its default
+/// partitioner uses to pick a partition for a key.
+pub fn murmur2(data: &[u8]) -> i32 {
+    const SEED: u32 = 0x9747_b28c;
+    const M: u32 = 0x5bd1_e995;
+    const R: u32 = 24;
+
+    let mut h = SEED ^ data.len() as u32;
+
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M);
+        h ^= k;
+    }
+
+    let rest = chunks.remainder();
+    if rest.len() >= 3 {
+        h ^= u32::from(rest[2]) << 16;
+    }
+    if rest.len() >= 2 {
+        h ^= u32::from(rest[1]) << 8;
+    }
+    if !rest.is_empty() {
+        h ^= u32::from(rest[0]);
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^= h >> 15;
+
+    h as i32
+}
+
+/// The partition the Java client's default partitioner writes a key to.
+pub fn partition_for_key(key: &[u8], partition_count: i32) -> i32 {
+    (murmur2(key) & 0x7fff_ffff) % partition_count
+}
+
+/// How messages without a key are spread over the partitions of a topic.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Partitioner {
+    /// Every message goes to the next partition.
+    #[default]
+    RoundRobin,
+    /// All messages of a request go to the same partition, the next request
+    /// moves on to the next one.
+    Sticky,
+}
+
+/// Picks the partition for every message of a request the way the Java
+/// client would, unless the message names one itself.
+pub struct PartitionAssigner<'a> {
+    topic: &'a str,
+    partition_count: i32,
+    partitioner: Partitioner,
+    sticky_partition: Option<i32>,
+}
+
+impl<'a> PartitionAssigner<'a> {
+    pub fn new(topic: &'a str, partition_count: i32, partitioner: Partitioner) -> Self {
+        PartitionAssigner {
+            topic,
+            partition_count,
+            partitioner,
+            sticky_partition: None,
+        }
+    }
+
+    pub fn partition(&mut self, key: Option<&[u8]>) -> i32 {
+        match (key, self.partitioner) {
+            (Some(key), _) => partition_for_key(key, self.partition_count),
+            (None, Partitioner::RoundRobin) => next_partition(self.topic, self.partition_count),
+            (None, Partitioner::Sticky) => {
+                let (topic, partition_count) = (self.topic, self.partition_count);
+                *self
+                    .sticky_partition
+                    .get_or_insert_with(|| next_partition(topic, partition_count))
+            }
+        }
+    }
+}
+
+/// Rotates over the partitions of a topic, across requests. Like the Java
+/// client, each topic starts at a random partition.
+fn next_partition(topic: &str, partition_count: i32) -> i32 {
+    static COUNTERS: OnceLock<Mutex<HashMap<String, u32>>> = OnceLock::new();
+
+    let mut counters = match COUNTERS.get_or_init(Default::default).lock() {
+        Ok(counters) => counters,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let counter = counters
+        .entry(String::from(topic))
+        .or_insert_with(rand::random);
+    let partition = (*counter & 0x7fff_ffff) as i32 % partition_count;
+    *counter = counter.wrapping_add(1);
+
+    partition
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_like_the_java_client() {
+        assert_eq!(murmur2(b"21"), -973_932_308);
+        assert_eq!(murmur2(b"foobar"), -790_332_482);
+        assert_eq!(murmur2(b"abc"), 479_470_107);
+    }
+
+    #[test]
+    fn picks_the_partition_of_the_java_client() {
+        // (-790332482 & 0x7fffffff) % 3, (479470107 & 0x7fffffff) % 10
+        assert_eq!(partition_for_key(b"foobar", 3), 0);
+        assert_eq!(partition_for_key(b"abc", 10), 7);
+        assert_eq!(partition_for_key(b"abc", 1), 0);
+    }
+
+    #[test]
+    fn keys_pick_their_partition_whatever_the_partitioner() {
+        for partitioner in &[Partitioner::RoundRobin, Partitioner::Sticky] {
+            let mut assigner = PartitionAssigner::new("orders", 10, *partitioner);
+
+            assert_eq!(assigner.partition(Some(b"abc")), 7);
+            assert_eq!(assigner.partition(Some(b"abc")), 7);
+        }
+    }
+
+    #[test]
+    fn sticks_to_one_partition_per_request() {
+        let mut assigner = PartitionAssigner::new("sticky-topic", 4, Partitioner::Sticky);
+        let partition = assigner.partition(None);
+
+        assert!((0..4).contains(&partition));
+        assert_eq!(assigner.partition(None), partition);
+    }
+
+    #[test]
+    fn rotates_over_the_partitions() {
+        let mut assigner = PartitionAssigner::new("round-robin-topic", 4, Partitioner::RoundRobin);
+        let first = assigner.partition(None);
+
+        assert_eq!(assigner.partition(None), (first + 1) % 4);
+        assert_eq!(assigner.partition(None), (first + 2) % 4);
+    }
+}
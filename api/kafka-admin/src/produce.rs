@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::env;
+use std::slice;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rdkafka::client::ClientContext;
+use rdkafka::error::{KafkaError, RDKafkaError};
+use rdkafka::message::{Message, OwnedHeaders};
+use rdkafka::producer::{BaseProducer, BaseRecord, DeliveryResult, ProducerContext};
+use serde::{Deserialize, Deserializer};
+
+use crate::partitioner::{PartitionAssigner, Partitioner};
+use crate::{create_config, fetch_topic_detail, message_timestamp, TimestampType};
+
+/// A message to produce. A message with a `null` value is a tombstone, which
+/// deletes its key once a compacted topic is cleaned. The value can't be left
+/// out, so a tombstone is never produced by accident.
+#[derive(Debug, Deserialize)]
+pub struct ProduceRecord {
+    /// Picked by the partitioner when left out.
+    pub partition: Option<i32>,
+    pub key: Option<String>,
+    #[serde(alias = "message", deserialize_with = "nullable")]
+    pub value: Option<String>,
+    #[serde(default)]
+    pub headers: Vec<ProduceHeader>,
+    /// The create time in millis, the time of sending when left out.
+    pub timestamp: Option<i64>,
+}
+
+/// Deserializes an `Option` that has to be present, as serde takes a missing
+/// `Option` for `None` unless it uses its own deserializer.
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProduceHeader {
+    pub name: String,
+    pub value: String,
+}
+
+/// How many replicas have to acknowledge a message before it counts as
+/// produced.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Acks {
+    /// Don't wait for the broker at all, the offset isn't known then.
+    None,
+    #[default]
+    Leader,
+    /// All in-sync replicas.
+    All,
+}
+
+impl Acks {
+    fn config_value(self) -> &'static str {
+        match self {
+            Acks::None => "0",
+            Acks::Leader => "1",
+            Acks::All => "all",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ProduceOptions {
+    pub acks: Acks,
+    /// How long to wait for the message to be acknowledged, up to the maximum
+    /// configured with `MAX_PRODUCE_TIMEOUT_MS`.
+    pub timeout: Duration,
+    /// Messages with a key always go to the partition the Java client would
+    /// write them to.
+    pub partitioner: Partitioner,
+}
+
+const DEFAULT_PRODUCE_TIMEOUT_MS: u64 = 5000;
+
+impl ProduceOptions {
+    pub fn new(
+        acks: Option<Acks>,
+        timeout_ms: Option<u64>,
+        partitioner: Option<Partitioner>,
+    ) -> Self {
+        let max_timeout_ms = env::var("MAX_PRODUCE_TIMEOUT_MS")
+            .ok()
+            .and_then(|timeout| timeout.parse().ok())
+            .unwrap_or(60_000);
+
+        ProduceOptions {
+            acks: acks.unwrap_or_default(),
+            timeout: Duration::from_millis(
+                timeout_ms
+                    .unwrap_or(DEFAULT_PRODUCE_TIMEOUT_MS)
+                    .min(max_timeout_ms)
+                    .max(1),
+            ),
+            partitioner: partitioner.unwrap_or_default(),
+        }
+    }
+}
+
+impl Default for ProduceOptions {
+    fn default() -> Self {
+        ProduceOptions::new(None, None, None)
+    }
+}
+
+/// Where a message ended up.
+#[derive(Debug, Serialize)]
+pub struct ProduceResponse {
+    partition: i32,
+    /// Unknown when produced without waiting for acks.
+    offset: Option<i64>,
+    /// The create time, or the time the broker appended the message when the
+    /// topic uses `LogAppendTime`.
+    timestamp: Option<i64>,
+    timestamp_type: TimestampType,
+    timestamp_iso: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProduceErrorKind {
+    /// The message wasn't acknowledged in time.
+    Timeout,
+    UnknownPartition,
+    /// The broker refused the message, e.g. because it's too large, or the
+    /// record in the request couldn't be read.
+    InvalidRecord,
+    Other,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProduceError {
+    error: String,
+    /// The Kafka error code. Negative codes are raised by the client rather
+    /// than the broker.
+    code: Option<i32>,
+    kind: ProduceErrorKind,
+}
+
+impl ProduceError {
+    fn not_delivered() -> Self {
+        ProduceError {
+            error: String::from("Message wasn't delivered in time"),
+            code: None,
+            kind: ProduceErrorKind::Timeout,
+        }
+    }
+
+    fn unknown_topic() -> Self {
+        ProduceError {
+            error: String::from("Can't find topic"),
+            code: None,
+            kind: ProduceErrorKind::UnknownPartition,
+        }
+    }
+
+    /// A record in the request that couldn't be read, so nothing was sent.
+    pub fn invalid_record(error: String) -> Self {
+        ProduceError {
+            error,
+            code: None,
+            kind: ProduceErrorKind::InvalidRecord,
+        }
+    }
+
+    pub fn kind(&self) -> ProduceErrorKind {
+        self.kind
+    }
+
+    fn from_kafka_error(e: &KafkaError) -> Self {
+        let code = match e {
+            KafkaError::MessageProduction(code) => Some(*code),
+            _ => None,
+        };
+        let kind = match code {
+            Some(RDKafkaError::MessageTimedOut) | Some(RDKafkaError::RequestTimedOut) => {
+                ProduceErrorKind::Timeout
+            }
+            Some(RDKafkaError::UnknownPartition)
+            | Some(RDKafkaError::UnknownTopic)
+            | Some(RDKafkaError::UnknownTopicOrPartition) => ProduceErrorKind::UnknownPartition,
+            Some(RDKafkaError::MessageSizeTooLarge)
+            | Some(RDKafkaError::InvalidMessage)
+            | Some(RDKafkaError::InvalidTimestamp) => ProduceErrorKind::InvalidRecord,
+            _ => ProduceErrorKind::Other,
+        };
+
+        ProduceError {
+            error: e.to_string(),
+            code: code.map(|code| code as i32),
+            kind,
+        }
+    }
+}
+
+/// Keeps the delivery report of every message sent, by the index it was sent
+/// with.
+#[derive(Default)]
+struct DeliveryContext {
+    deliveries: Mutex<HashMap<usize, Result<ProduceResponse, ProduceError>>>,
+}
+
+impl ClientContext for DeliveryContext {}
+
+impl ProducerContext for DeliveryContext {
+    type DeliveryOpaque = usize;
+
+    fn delivery(&self, result: &DeliveryResult, index: usize) {
+        let delivery = match result {
+            Ok(m) => {
+                let (timestamp, timestamp_type, timestamp_iso) = message_timestamp(m);
+                Ok(ProduceResponse {
+                    partition: m.partition(),
+                    offset: Some(m.offset()).filter(|offset| *offset >= 0),
+                    timestamp,
+                    timestamp_type,
+                    timestamp_iso,
+                })
+            }
+            Err((e, _)) => Err(ProduceError::from_kafka_error(e)),
+        };
+
+        if let Ok(mut deliveries) = self.deliveries.lock() {
+            deliveries.insert(index, delivery);
+        }
+    }
+}
+
+/// Produces a message and waits until it's acknowledged. Errors reported by
+/// Kafka are returned as a [`ProduceError`], failing to set up the producer
+/// as an `Err`.
+pub fn produce(
+    topic: &str,
+    record: &ProduceRecord,
+    options: &ProduceOptions,
+) -> Result<Result<ProduceResponse, ProduceError>, &'static str> {
+    let mut results = produce_records(topic, slice::from_ref(record), options)?;
+
+    results.pop().ok_or("Message wasn't produced")
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BatchRecordResult {
+    Produced(ProduceResponse),
+    Failed(ProduceError),
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchFailure {
+    error: String,
+    code: Option<i32>,
+    /// The number of messages that failed with this error.
+    count: usize,
+    /// The index of the first message that failed with this error.
+    first_index: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchProduceResponse {
+    /// A result for every message, in the order they were sent.
+    results: Vec<BatchRecordResult>,
+    produced: usize,
+    failed: usize,
+    failures: Vec<BatchFailure>,
+}
+
+/// Produces messages with a single producer and waits until all of them are
+/// acknowledged. Messages are sent in order, but messages for the same
+/// partition only keep that order when none of them fail.
+pub fn produce_batch(
+    topic: &str,
+    records: &[ProduceRecord],
+    options: &ProduceOptions,
+) -> Result<BatchProduceResponse, &'static str> {
+    let results = produce_records(topic, records, options)?;
+
+    let mut failures: Vec<BatchFailure> = vec![];
+    for (index, result) in results.iter().enumerate() {
+        if let Err(e) = result {
+            match failures
+                .iter_mut()
+                .find(|failure| failure.error == e.error && failure.code == e.code)
+            {
+                Some(failure) => failure.count += 1,
+                None => failures.push(BatchFailure {
+                    error: e.error.clone(),
+                    code: e.code,
+                    count: 1,
+                    first_index: index,
+                }),
+            }
+        }
+    }
+    let failed: usize = failures.iter().map(|failure| failure.count).sum();
+
+    Ok(BatchProduceResponse {
+        produced: results.len() - failed,
+        failed,
+        failures,
+        results: results
+            .into_iter()
+            .map(|result| match result {
+                Ok(produced) => BatchRecordResult::Produced(produced),
+                Err(e) => BatchRecordResult::Failed(e),
+            })
+            .collect(),
+    })
+}
+
+/// Returns a result for every record, by the index it had in `records`.
+fn produce_records(
+    topic: &str,
+    records: &[ProduceRecord],
+    options: &ProduceOptions,
+) -> Result<Vec<Result<ProduceResponse, ProduceError>>, &'static str> {
+    let timeout_ms = options.timeout.as_millis().to_string();
+    let producer: BaseProducer<DeliveryContext> = create_config()
+        .set("acks", options.acks.config_value())
+        .set("request.timeout.ms", &timeout_ms)
+        .set("message.timeout.ms", &timeout_ms)
+        .create_with_context(DeliveryContext::default())
+        .map_err(|_| "Producer creation failed")?;
+
+    // The partition count is only needed for messages that don't name a
+    // partition themselves.
+    let mut assigner = None;
+    if records.iter().any(|record| record.partition.is_none()) {
+        let topics_detail = fetch_topic_detail(Some(topic))?;
+        assigner = topics_detail
+            .first()
+            .filter(|topic_detail| !topic_detail.partition_details.is_empty())
+            .map(|topic_detail| {
+                let partition_count = topic_detail.partition_details.len() as i32;
+                PartitionAssigner::new(topic, partition_count, options.partitioner)
+            });
+    }
+
+    let mut send_errors: HashMap<usize, ProduceError> = HashMap::new();
+    for (index, record) in records.iter().enumerate() {
+        let partition = match (record.partition, assigner.as_mut()) {
+            (Some(partition), _) => partition,
+            (None, Some(assigner)) => {
+                assigner.partition(record.key.as_ref().map(|key| key.as_bytes()))
+            }
+            (None, None) => {
+                send_errors.insert(index, ProduceError::unknown_topic());
+                continue;
+            }
+        };
+
+        let headers = record
+            .headers
+            .iter()
+            .fold(OwnedHeaders::new(), |headers, header| {
+                headers.add(&header.name, header.value.as_str())
+            });
+
+        let mut message = BaseRecord::with_opaque_to(topic, index)
+            .partition(partition)
+            .headers(headers);
+        if let Some(key) = &record.key {
+            message = message.key(key.as_str());
+        }
+        if let Some(value) = &record.value {
+            message = message.payload(value.as_str());
+        }
+        if let Some(timestamp) = record.timestamp {
+            message = message.timestamp(timestamp);
+        }
+
+        // When the producer's queue is full, wait for some of the messages
+        // to be delivered and try again.
+        loop {
+            match producer.send(message) {
+                Ok(()) => break,
+                Err((KafkaError::MessageProduction(RDKafkaError::QueueFull), returned)) => {
+                    producer.poll(Duration::from_millis(100));
+                    message = returned;
+                }
+                Err((e, _)) => {
+                    send_errors.insert(index, ProduceError::from_kafka_error(&e));
+                    break;
+                }
+            }
+        }
+    }
+    // Messages time out on their own, the extra second leaves room for the
+    // delivery reports to come in.
+    producer.flush(options.timeout + Duration::from_secs(1));
+
+    let mut deliveries = producer
+        .context()
+        .deliveries
+        .lock()
+        .map_err(|_| "Failed to read delivery reports")?;
+
+    Ok((0..records.len())
+        .map(|index| match send_errors.remove(&index) {
+            Some(e) => Err(e),
+            None => deliveries
+                .remove(&index)
+                .unwrap_or_else(|| Err(ProduceError::not_delivered())),
+        })
+        .collect())
+}
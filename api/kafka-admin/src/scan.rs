@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::error::KafkaError;
+use rdkafka::message::{BorrowedMessage, Message};
+use rdkafka::topic_partition_list::Offset::Offset;
+use rdkafka::TopicPartitionList;
+
+use crate::create_config;
+use crate::transactions::IsolationLevel;
+
+/// A range of offsets to read from a partition, `end` is exclusive.
+#[derive(Debug, Clone, Copy)]
+pub struct PartitionRange {
+    pub partition: i32,
+    pub start: i64,
+    pub end: i64,
+}
+
+pub enum ScanEvent<'a> {
+    Message(&'a BorrowedMessage<'a>),
+    /// No more messages will be read from the partition. `reached_end` is
+    /// false when the partition ran out of messages before the end of its
+    /// range.
+    PartitionEnd {
+        partition: i32,
+        reached_end: bool,
+    },
+}
+
+/// How long to wait for a message before giving up on a scan.
+const SCAN_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Creates a consumer that is only used with manually assigned partitions and
+/// never commits offsets.
+pub fn create_assign_consumer() -> Result<BaseConsumer, &'static str> {
+    create_isolated_consumer(IsolationLevel::ReadCommitted)
+}
+
+/// Like [`create_assign_consumer`], reading either only committed messages
+/// or everything including aborted and open transactions.
+pub fn create_isolated_consumer(isolation: IsolationLevel) -> Result<BaseConsumer, &'static str> {
+    create_config()
+        .set("group.id", "kafka-onion")
+        .set("enable.auto.commit", "false")
+        .set("enable.partition.eof", "true")
+        .set("isolation.level", isolation.config_value())
+        .create()
+        .map_err(|_| "Consumer creation failed")
+}
+
+/// Reads every message in the ranges, in offset order per partition, and
+/// hands it to `on_event` until all ranges are done or it returns false.
+///
+/// A range is done when its end offset is reached or the partition runs out
+/// of messages before that, which happens when the last offsets hold
+/// transaction markers or were removed by compaction.
+pub fn scan<F>(topic: &str, ranges: &[PartitionRange], on_event: F) -> Result<(), &'static str>
+where
+    F: FnMut(ScanEvent) -> bool,
+{
+    scan_isolated(topic, ranges, IsolationLevel::ReadCommitted, on_event)
+}
+
+/// Like [`scan`], with the given isolation level.
+pub fn scan_isolated<F>(
+    topic: &str,
+    ranges: &[PartitionRange],
+    isolation: IsolationLevel,
+    mut on_event: F,
+) -> Result<(), &'static str>
+where
+    F: FnMut(ScanEvent) -> bool,
+{
+    let mut remaining: HashMap<i32, i64> = HashMap::new();
+    let mut tpl = TopicPartitionList::new();
+
+    for range in ranges {
+        if range.start >= range.end {
+            let end = ScanEvent::PartitionEnd {
+                partition: range.partition,
+                reached_end: true,
+            };
+            if !on_event(end) {
+                return Ok(());
+            }
+            continue;
+        }
+        remaining.insert(range.partition, range.end);
+        tpl.add_partition_offset(topic, range.partition, Offset(range.start));
+    }
+
+    if remaining.is_empty() {
+        return Ok(());
+    }
+
+    let consumer = create_isolated_consumer(isolation)?;
+    consumer
+        .assign(&tpl)
+        .map_err(|_| "Can't subscribe to specified partitions")?;
+
+    let mut last_event = Instant::now();
+    while !remaining.is_empty() {
+        let ended = match consumer.poll(Duration::from_millis(100)) {
+            None => {
+                if last_event.elapsed() > SCAN_IDLE_TIMEOUT {
+                    return Err("Timed out reading messages");
+                }
+                continue;
+            }
+            Some(Err(KafkaError::PartitionEOF(partition))) => Some((partition, false)),
+            Some(Err(e)) => {
+                eprintln!("Kafka error: {}", e);
+                None
+            }
+            Some(Ok(m)) => match remaining.get(&m.partition()) {
+                None => None,
+                Some(end) if m.offset() >= *end => Some((m.partition(), true)),
+                Some(end) => {
+                    let last = m.offset() + 1 >= *end;
+                    if !on_event(ScanEvent::Message(&m)) {
+                        return Ok(());
+                    }
+                    if last {
+                        Some((m.partition(), true))
+                    } else {
+                        None
+                    }
+                }
+            },
+        };
+        last_event = Instant::now();
+
+        if let Some((partition, reached_end)) = ended {
+            if remaining.remove(&partition).is_some()
+                && !on_event(ScanEvent::PartitionEnd {
+                    partition,
+                    reached_end,
+                })
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    Ok(())
+}
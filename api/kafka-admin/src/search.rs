@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use payload_decoder::get_max_listed_payload_bytes;
+use rdkafka::message::Message;
+use regex::Regex;
+
+use crate::partitioner::partition_for_key;
+use crate::scan::{scan, PartitionRange, ScanEvent};
+use crate::{fetch_topic_detail, offsets_for_timestamp, MessageResponse};
+
+pub enum SearchPattern {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl SearchPattern {
+    fn matches(&self, text: &str) -> bool {
+        match self {
+            SearchPattern::Substring(substring) => text.contains(substring.as_str()),
+            SearchPattern::Regex(regex) => regex.is_match(text),
+        }
+    }
+
+    /// Whether the key, the decoded value or any header name or value match.
+    fn matches_message(&self, message: &MessageResponse) -> bool {
+        message
+            .key
+            .as_ref()
+            .is_some_and(|key| self.matches(&key.data))
+            || self.matches(&message.payload.json)
+            || message
+                .headers
+                .iter()
+                .any(|header| self.matches(&header.name) || self.matches(&header.value.data))
+    }
+}
+
+/// What to search for, optionally limited to a range of offsets (applied to
+/// every partition, `to_offset` is exclusive) and/or a range of timestamps.
+pub struct SearchRequest {
+    pub pattern: SearchPattern,
+    pub from_offset: Option<i64>,
+    pub to_offset: Option<i64>,
+    pub from_timestamp: Option<i64>,
+    pub to_timestamp: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SearchEvent {
+    Match(MessageResponse),
+    Progress(PartitionProgress),
+    Done {
+        scanned_messages: usize,
+        matches: usize,
+    },
+    Error {
+        error: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PartitionProgress {
+    partition: i32,
+    start_offset: i64,
+    end_offset: i64,
+    /// The offset of the last message that was read.
+    offset: Option<i64>,
+    scanned_messages: usize,
+    done: bool,
+}
+
+/// How many messages to read from a partition between progress events.
+const PROGRESS_INTERVAL: usize = 1000;
+
+/// Searches all partitions of a topic, sending matches and progress to
+/// `on_event` as they're found. Stops early when `on_event` returns false,
+/// e.g. when the client went away.
+pub fn search<F>(topic: &str, request: &SearchRequest, mut on_event: F) -> Result<(), &'static str>
+where
+    F: FnMut(SearchEvent) -> bool,
+{
+    let topics_detail = fetch_topic_detail(Some(topic))?;
+    let topic_detail = topics_detail.first().ok_or("Can't find topic")?;
+    let partitions = &topic_detail.partition_details;
+
+    let from_timestamp = match request.from_timestamp {
+        Some(timestamp) => Some(offsets_for_timestamp(topic, partitions, timestamp)?),
+        None => None,
+    };
+    let to_timestamp = match request.to_timestamp {
+        Some(timestamp) => Some(offsets_for_timestamp(topic, partitions, timestamp)?),
+        None => None,
+    };
+
+    let ranges: Vec<PartitionRange> = partitions
+        .iter()
+        .map(|p| {
+            let mut start = p.lowwatermark_offset;
+            let mut end = p.highwatermark_offset;
+
+            if let Some(offset) = request.from_offset {
+                start = start.max(offset);
+            }
+            if let Some(offset) = request.to_offset {
+                end = end.min(offset);
+            }
+            if let Some(offset) = from_timestamp.as_ref().and_then(|o| o.get(&p.id)) {
+                start = start.max(*offset);
+            }
+            if let Some(offset) = to_timestamp.as_ref().and_then(|o| o.get(&p.id)) {
+                end = end.min(*offset);
+            }
+
+            PartitionRange {
+                partition: p.id,
+                start,
+                end,
+            }
+        })
+        .collect();
+
+    let mut progress: HashMap<i32, PartitionProgress> = ranges
+        .iter()
+        .map(|range| {
+            let progress = PartitionProgress {
+                partition: range.partition,
+                start_offset: range.start,
+                end_offset: range.end,
+                offset: None,
+                scanned_messages: 0,
+                done: false,
+            };
+            (range.partition, progress)
+        })
+        .collect();
+
+    let mut scanned_messages = 0;
+    let mut matches = 0;
+    let mut stopped = false;
+
+    scan(topic, &ranges, |event| {
+        let keep_going = match event {
+            ScanEvent::Message(m) => {
+                let message = MessageResponse::from_message(topic, m);
+                scanned_messages += 1;
+
+                let partition = match progress.get_mut(&message.partition) {
+                    Some(partition) => partition,
+                    None => return true,
+                };
+                partition.offset = Some(message.offset);
+                partition.scanned_messages += 1;
+                let report_progress = partition.scanned_messages % PROGRESS_INTERVAL == 0;
+                let partition = partition.clone();
+
+                let matched = request.pattern.matches_message(&message);
+                if matched {
+                    matches += 1;
+                }
+
+                (!matched || on_event(SearchEvent::Match(message)))
+                    && (!report_progress || on_event(SearchEvent::Progress(partition)))
+            }
+            ScanEvent::PartitionEnd { partition, .. } => match progress.get_mut(&partition) {
+                Some(partition) => {
+                    partition.done = true;
+                    on_event(SearchEvent::Progress(partition.clone()))
+                }
+                None => true,
+            },
+        };
+        stopped = !keep_going;
+
+        keep_going
+    })?;
+
+    if !stopped {
+        on_event(SearchEvent::Done {
+            scanned_messages,
+            matches,
+        });
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyMessagesResponse {
+    /// The partitions that were scanned.
+    partitions: Vec<i32>,
+    messages: Vec<MessageResponse>,
+}
+
+/// Finds every message with the given key. Only the partition the Java
+/// client's default partitioner picks for the key is scanned, unless
+/// `all_partitions` is set for topics written with a custom partitioner.
+///
+/// Messages are returned in offset order per partition.
+pub fn search_key(
+    topic: &str,
+    key: &[u8],
+    all_partitions: bool,
+) -> Result<KeyMessagesResponse, &'static str> {
+    let topics_detail = fetch_topic_detail(Some(topic))?;
+    let topic_detail = topics_detail.first().ok_or("Can't find topic")?;
+    let partitions = &topic_detail.partition_details;
+    if partitions.is_empty() {
+        return Err("Topic has no partitions");
+    }
+
+    let target = partition_for_key(key, partitions.len() as i32);
+    let ranges: Vec<PartitionRange> = partitions
+        .iter()
+        .filter(|p| all_partitions || p.id == target)
+        .map(|p| PartitionRange {
+            partition: p.id,
+            start: p.lowwatermark_offset,
+            end: p.highwatermark_offset,
+        })
+        .collect();
+
+    let max_payload_bytes = get_max_listed_payload_bytes();
+    let mut messages = vec![];
+    scan(topic, &ranges, |event| {
+        if let ScanEvent::Message(m) = event {
+            if m.key() == Some(key) {
+                let mut message = MessageResponse::from_message(topic, m);
+                message.payload.truncate(max_payload_bytes);
+                messages.push(message);
+            }
+        }
+        true
+    })?;
+    messages.sort_by_key(|m| (m.partition, m.offset));
+
+    Ok(KeyMessagesResponse {
+        partitions: ranges.iter().map(|range| range.partition).collect(),
+        messages,
+    })
+}
@@ -0,0 +1,232 @@
+use std::collections::{HashMap, HashSet};
+
+use paging::max_page_limit;
+use payload_decoder::get_max_listed_payload_bytes;
+use rdkafka::message::Message;
+
+use crate::scan::{scan, PartitionRange, ScanEvent};
+use crate::{fetch_topic_detail, offsets_for_timestamp, MessageResponse, PartitionOffsets};
+
+/// Which part of the log to materialize and which page of keys to return.
+pub struct TableRequest {
+    pub from_offset: Option<i64>,
+    /// Only messages before this offset are read, in every partition.
+    pub as_of_offset: Option<i64>,
+    /// Only messages up to and including this timestamp (in millis) are read.
+    pub as_of_timestamp: Option<i64>,
+    pub include_tombstones: bool,
+    /// Only keys sorting after these bytes are returned, used for paging.
+    pub after_key: Option<Vec<u8>>,
+    /// Rows per page, kept between 1 and `MAX_PAGE_LIMIT`.
+    pub limit: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableRow {
+    #[serde(flatten)]
+    message: MessageResponse,
+    /// The latest message for the key has no value, so the key is deleted
+    /// once the topic is compacted.
+    tombstone: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableResponse {
+    rows: Vec<TableRow>,
+    /// Pass as `after_key` to get the next page, `None` on the last page. The
+    /// key's bytes in URL safe base64.
+    next_key: Option<String>,
+    total_keys: usize,
+    tombstones: usize,
+    scanned_messages: usize,
+    /// Messages without a key can't be part of the table.
+    keyless_messages: usize,
+    /// The offsets per partition the table was read up to, exclusive.
+    end_offsets: PartitionOffsets,
+}
+
+/// Materializes a compacted topic into its latest value per key, the way a
+/// consumer would see it after reading the topic up to the given point.
+///
+/// Keys are sorted by their bytes. With the default partitioner a key lives
+/// in a single partition; when it shows up in several the message with the
+/// latest timestamp wins.
+pub fn materialize_table(
+    topic: &str,
+    request: &TableRequest,
+) -> Result<TableResponse, &'static str> {
+    let topics_detail = fetch_topic_detail(Some(topic))?;
+    let topic_detail = topics_detail.first().ok_or("Can't find topic")?;
+    let partitions = &topic_detail.partition_details;
+
+    let as_of_timestamp = match request.as_of_timestamp {
+        Some(timestamp) => Some(offsets_for_timestamp(topic, partitions, timestamp + 1)?),
+        None => None,
+    };
+
+    let ranges: Vec<PartitionRange> = partitions
+        .iter()
+        .map(|p| {
+            let mut start = p.lowwatermark_offset;
+            let mut end = p.highwatermark_offset;
+
+            if let Some(offset) = request.from_offset {
+                start = start.max(offset);
+            }
+            if let Some(offset) = request.as_of_offset {
+                end = end.min(offset);
+            }
+            if let Some(offset) = as_of_timestamp.as_ref().and_then(|o| o.get(&p.id)) {
+                end = end.min(*offset);
+            }
+
+            PartitionRange {
+                partition: p.id,
+                start,
+                end,
+            }
+        })
+        .collect();
+
+    // Only where the latest message per key is gets kept, the messages on
+    // the page that's returned are read again afterwards.
+    let mut latest: HashMap<Vec<u8>, LatestMessage> = HashMap::new();
+    let mut scanned_messages = 0;
+    let mut keyless_messages = 0;
+
+    scan(topic, &ranges, |event| {
+        if let ScanEvent::Message(m) = event {
+            scanned_messages += 1;
+
+            let key = match m.key() {
+                Some(key) => key,
+                None => {
+                    keyless_messages += 1;
+                    return true;
+                }
+            };
+
+            let timestamp = m.timestamp().to_millis();
+            let newer = match latest.get(key) {
+                None => true,
+                Some(previous) => {
+                    previous.partition == m.partition() || previous.timestamp <= timestamp
+                }
+            };
+            if newer {
+                let message = LatestMessage {
+                    partition: m.partition(),
+                    offset: m.offset(),
+                    timestamp,
+                    tombstone: m.payload().is_none(),
+                };
+                latest.insert(key.to_vec(), message);
+            }
+        }
+        true
+    })?;
+
+    let total_keys = latest.len();
+    let tombstones = latest.values().filter(|m| m.tombstone).count();
+
+    let mut keys: Vec<(Vec<u8>, LatestMessage)> = latest
+        .into_iter()
+        .filter(|(_, m)| request.include_tombstones || !m.tombstone)
+        .filter(|(key, _)| match &request.after_key {
+            Some(after_key) => key > after_key,
+            None => true,
+        })
+        .collect();
+    keys.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let limit = request.limit.min(max_page_limit()).max(1);
+    let next_key = if keys.len() > limit {
+        keys.truncate(limit);
+        keys.last().map(|(key, _)| encode_key(key))
+    } else {
+        None
+    };
+
+    Ok(TableResponse {
+        rows: read_rows(topic, &keys)?,
+        next_key,
+        total_keys,
+        tombstones,
+        scanned_messages,
+        keyless_messages,
+        end_offsets: ranges
+            .iter()
+            .map(|range| (range.partition, range.end))
+            .collect(),
+    })
+}
+
+/// Where the latest message for a key is.
+struct LatestMessage {
+    partition: i32,
+    offset: i64,
+    timestamp: Option<i64>,
+    tombstone: bool,
+}
+
+/// Reads the latest messages of the keys on a page, in the order of the
+/// keys. Only the part of each partition between the first and the last of
+/// them is scanned.
+fn read_rows(
+    topic: &str,
+    page: &[(Vec<u8>, LatestMessage)],
+) -> Result<Vec<TableRow>, &'static str> {
+    let mut ranges: HashMap<i32, PartitionRange> = HashMap::new();
+    for (_, latest) in page {
+        let range = ranges.entry(latest.partition).or_insert(PartitionRange {
+            partition: latest.partition,
+            start: latest.offset,
+            end: latest.offset + 1,
+        });
+        range.start = range.start.min(latest.offset);
+        range.end = range.end.max(latest.offset + 1);
+    }
+    let ranges: Vec<PartitionRange> = ranges.into_values().collect();
+
+    let wanted: HashSet<(i32, i64)> = page
+        .iter()
+        .map(|(_, latest)| (latest.partition, latest.offset))
+        .collect();
+
+    let max_payload_bytes = get_max_listed_payload_bytes();
+    let mut messages: HashMap<(i32, i64), MessageResponse> = HashMap::new();
+    scan(topic, &ranges, |event| {
+        if let ScanEvent::Message(m) = event {
+            if wanted.contains(&(m.partition(), m.offset())) {
+                let mut message = MessageResponse::from_message(topic, m);
+                message.payload.truncate(max_payload_bytes);
+                messages.insert((m.partition(), m.offset()), message);
+            }
+        }
+        true
+    })?;
+
+    // A message that's gone by now, because the topic was truncated in the
+    // meantime, leaves out its row.
+    Ok(page
+        .iter()
+        .filter_map(|(_, latest)| {
+            let message = messages.remove(&(latest.partition, latest.offset))?;
+            Some(TableRow {
+                message,
+                tombstone: latest.tombstone,
+            })
+        })
+        .collect())
+}
+
+/// Keys are handed to clients as URL safe base64 of their bytes, as their
+/// rendered form doesn't tell text and binary keys apart.
+fn encode_key(key: &[u8]) -> String {
+    base64::encode_config(key, base64::URL_SAFE_NO_PAD)
+}
+
+/// Reads a key from a table's `next_key`.
+pub fn decode_table_key(key: &str) -> Result<Vec<u8>, &'static str> {
+    base64::decode_config(key, base64::URL_SAFE_NO_PAD).map_err(|_| "Invalid table key")
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+use payload_decoder::get_max_listed_payload_bytes;
+use rdkafka::consumer::Consumer;
+use rdkafka::error::KafkaError;
+use rdkafka::message::Message;
+use rdkafka::topic_partition_list::Offset;
+use rdkafka::TopicPartitionList;
+
+use crate::scan::create_assign_consumer;
+use crate::{fetch_topic_detail, offsets_for_timestamp, Filter, MessageResponse};
+
+pub enum TailEvent {
+    Message {
+        topic: String,
+        message: MessageResponse,
+    },
+    /// Nothing was sent for a while, sent so a client that went away is
+    /// noticed even when no messages match.
+    Heartbeat,
+    /// A command couldn't be carried out, tailing goes on.
+    Error(&'static str),
+}
+
+/// Changes what a running [`tail_topics`] follows.
+pub enum TailCommand {
+    /// Starts following a topic from its current end.
+    Subscribe {
+        topic: String,
+        filter: Option<Filter>,
+    },
+    Unsubscribe {
+        topic: String,
+    },
+    SetFilter {
+        topic: String,
+        filter: Option<Filter>,
+    },
+    /// Pauses a topic, or all topics without one. Messages that arrive while
+    /// paused are sent after resuming.
+    Pause {
+        topic: Option<String>,
+    },
+    Resume {
+        topic: Option<String>,
+    },
+    /// Moves a partition, or all partitions of the topic without one.
+    Seek {
+        topic: String,
+        partition: Option<i32>,
+        to: SeekPosition,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeekPosition {
+    Beginning,
+    End,
+    Offset(i64),
+    /// The first message at or after the timestamp, in millis.
+    Timestamp(i64),
+}
+
+struct TopicTail {
+    filter: Option<Filter>,
+    paused: bool,
+    /// The next offset to read per partition.
+    positions: HashMap<i32, Offset>,
+}
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Follows all partitions of a topic from their current end, handing every
+/// new message that matches the filter to `on_event`. Runs until `on_event`
+/// returns false, which drops the consumer.
+pub fn tail<F>(topic: &str, filter: Option<Filter>, mut on_event: F) -> Result<(), &'static str>
+where
+    F: FnMut(TailEvent) -> bool,
+{
+    let (commands, receiver) = channel();
+    let subscribe = TailCommand::Subscribe {
+        topic: String::from(topic),
+        filter,
+    };
+    commands
+        .send(subscribe)
+        .map_err(|_| "Can't subscribe to topic")?;
+
+    let mut failed = None;
+    tail_topics(receiver, |event| match event {
+        TailEvent::Error(e) => {
+            failed = Some(e);
+            false
+        }
+        event => on_event(event),
+    })?;
+
+    match failed {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Follows the topics that are subscribed to through `commands`, handing
+/// every new message that matches the filter of its topic to `on_event`.
+/// Runs until `on_event` returns false or `commands` is disconnected.
+///
+/// All topics share a single consumer. Every change is applied by assigning
+/// it the partitions of the topics that aren't paused again, at the
+/// positions that were read up to.
+pub fn tail_topics<F>(commands: Receiver<TailCommand>, mut on_event: F) -> Result<(), &'static str>
+where
+    F: FnMut(TailEvent) -> bool,
+{
+    let consumer = create_assign_consumer()?;
+    let mut topics: HashMap<String, TopicTail> = HashMap::new();
+
+    let max_payload_bytes = get_max_listed_payload_bytes();
+    let mut last_event = Instant::now();
+    loop {
+        let mut assignment_changed = false;
+        loop {
+            match commands.try_recv() {
+                Ok(command) => match apply_command(&mut topics, command) {
+                    Ok(changed) => assignment_changed = assignment_changed || changed,
+                    Err(e) => {
+                        if !on_event(TailEvent::Error(e)) {
+                            return Ok(());
+                        }
+                    }
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+        if assignment_changed {
+            consumer
+                .assign(&assignment(&topics))
+                .map_err(|_| "Can't subscribe to specified partitions")?;
+        }
+
+        let event = match consumer.poll(Duration::from_millis(100)) {
+            None | Some(Err(KafkaError::PartitionEOF(_))) => None,
+            Some(Err(e)) => {
+                eprintln!("Kafka error: {}", e);
+                None
+            }
+            Some(Ok(m)) => match topics.get_mut(m.topic()) {
+                Some(topic) if !topic.paused => {
+                    topic
+                        .positions
+                        .insert(m.partition(), Offset::Offset(m.offset() + 1));
+
+                    let mut message = MessageResponse::from_message(m.topic(), &m);
+                    let matched = match &topic.filter {
+                        None => true,
+                        Some(filter) => filter.matches_str(&message.payload.json),
+                    };
+                    if matched {
+                        message.payload.truncate(max_payload_bytes);
+                        Some(TailEvent::Message {
+                            topic: String::from(m.topic()),
+                            message,
+                        })
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            },
+        };
+        // Also sent while messages arrive that don't match, so a client that
+        // went away is noticed on a busy topic too.
+        let event = match event {
+            Some(event) => event,
+            None if last_event.elapsed() >= HEARTBEAT_INTERVAL => TailEvent::Heartbeat,
+            None => continue,
+        };
+        last_event = Instant::now();
+
+        if !on_event(event) {
+            return Ok(());
+        }
+    }
+}
+
+/// Applies a command, returning whether the consumer needs a new assignment.
+fn apply_command(
+    topics: &mut HashMap<String, TopicTail>,
+    command: TailCommand,
+) -> Result<bool, &'static str> {
+    match command {
+        TailCommand::Subscribe { topic, filter } => {
+            let topics_detail = fetch_topic_detail(Some(&topic))?;
+            let topic_detail = topics_detail
+                .first()
+                .filter(|t| !t.partition_details.is_empty())
+                .ok_or("Can't find topic")?;
+            // Starting at the high watermarks rather than at the end keeps
+            // the messages that arrive before the consumer is assigned.
+            let positions = topic_detail
+                .partition_details
+                .iter()
+                .map(|p| (p.id, Offset::Offset(p.highwatermark_offset)))
+                .collect();
+
+            topics.insert(
+                topic,
+                TopicTail {
+                    filter,
+                    paused: false,
+                    positions,
+                },
+            );
+            Ok(true)
+        }
+        TailCommand::Unsubscribe { topic } => Ok(topics.remove(&topic).is_some()),
+        TailCommand::SetFilter { topic, filter } => {
+            let tail = topics.get_mut(&topic).ok_or("Not subscribed to topic")?;
+            tail.filter = filter;
+            Ok(false)
+        }
+        TailCommand::Pause { topic } => set_paused(topics, topic, true),
+        TailCommand::Resume { topic } => set_paused(topics, topic, false),
+        TailCommand::Seek {
+            topic,
+            partition,
+            to,
+        } => {
+            let tail = topics.get_mut(&topic).ok_or("Not subscribed to topic")?;
+            let timestamp_offsets = match to {
+                SeekPosition::Timestamp(timestamp) => {
+                    let topics_detail = fetch_topic_detail(Some(&topic))?;
+                    let topic_detail = topics_detail.first().ok_or("Can't find topic")?;
+                    Some(offsets_for_timestamp(
+                        &topic,
+                        &topic_detail.partition_details,
+                        timestamp,
+                    )?)
+                }
+                _ => None,
+            };
+
+            for (id, position) in tail.positions.iter_mut() {
+                if partition.is_some() && partition != Some(*id) {
+                    continue;
+                }
+                *position = match to {
+                    SeekPosition::Beginning => Offset::Beginning,
+                    SeekPosition::End => Offset::End,
+                    SeekPosition::Offset(offset) => Offset::Offset(offset),
+                    SeekPosition::Timestamp(_) => timestamp_offsets
+                        .as_ref()
+                        .and_then(|offsets| offsets.get(id))
+                        .map_or(Offset::End, |offset| Offset::Offset(*offset)),
+                };
+            }
+            Ok(true)
+        }
+    }
+}
+
+fn set_paused(
+    topics: &mut HashMap<String, TopicTail>,
+    topic: Option<String>,
+    paused: bool,
+) -> Result<bool, &'static str> {
+    match topic {
+        None => topics.values_mut().for_each(|tail| tail.paused = paused),
+        Some(topic) => {
+            topics
+                .get_mut(&topic)
+                .ok_or("Not subscribed to topic")?
+                .paused = paused
+        }
+    }
+
+    Ok(true)
+}
+
+fn assignment(topics: &HashMap<String, TopicTail>) -> TopicPartitionList {
+    let mut tpl = TopicPartitionList::new();
+    for (topic, tail) in topics.iter().filter(|(_, tail)| !tail.paused) {
+        for (partition, position) in &tail.positions {
+            tpl.add_partition_offset(topic, *partition, *position);
+        }
+    }
+
+    tpl
+}
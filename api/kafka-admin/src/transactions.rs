@@ -0,0 +1,131 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use rdkafka::consumer::Consumer;
+use rdkafka::message::Message;
+
+use crate::scan::{create_isolated_consumer, scan_isolated, PartitionRange, ScanEvent};
+use crate::MessageResponse;
+
+/// Which messages of transactional producers are read. Messages of
+/// producers that don't use transactions are always read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IsolationLevel {
+    /// Only messages of committed transactions, up to the first transaction
+    /// that's still open.
+    #[default]
+    ReadCommitted,
+    /// All messages, including those of aborted and open transactions.
+    ReadUncommitted,
+}
+
+impl IsolationLevel {
+    pub fn config_value(self) -> &'static str {
+        match self {
+            IsolationLevel::ReadCommitted => "read_committed",
+            IsolationLevel::ReadUncommitted => "read_uncommitted",
+        }
+    }
+}
+
+/// Whether a message read uncommitted is visible to read committed
+/// consumers. Messages without a transaction count as committed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionStatus {
+    Committed,
+    Aborted,
+    /// The transaction hasn't been committed or aborted yet.
+    Open,
+}
+
+/// An offset without a message, holding the commit or abort marker of a
+/// transaction. Offsets removed by compaction can't be told apart from
+/// markers, so they show up as markers too.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionMarker {
+    partition: i32,
+    offset: i64,
+}
+
+/// Sets the transaction status of messages that were read uncommitted and
+/// returns the markers between them.
+///
+/// Consumers never get to see control batches or tell aborted messages
+/// apart, so the offset range of the messages is read again: once
+/// uncommitted to find the offsets without a message and once committed to
+/// find the messages that were aborted.
+pub fn annotate_transactions(
+    topic: &str,
+    messages: &mut [MessageResponse],
+) -> Result<Vec<TransactionMarker>, &'static str> {
+    let mut bounds: HashMap<i32, (i64, i64)> = HashMap::new();
+    for message in messages.iter() {
+        let bound = bounds
+            .entry(message.partition)
+            .or_insert((message.offset, message.offset));
+        bound.0 = bound.0.min(message.offset);
+        bound.1 = bound.1.max(message.offset);
+    }
+    let ranges: Vec<PartitionRange> = bounds
+        .iter()
+        .map(|(partition, (first, last))| PartitionRange {
+            partition: *partition,
+            start: *first,
+            end: last + 1,
+        })
+        .collect();
+
+    let uncommitted = read_offsets(topic, &ranges, IsolationLevel::ReadUncommitted)?;
+    let committed = read_offsets(topic, &ranges, IsolationLevel::ReadCommitted)?;
+
+    // Read committed consumers get the last stable offset as high watermark,
+    // everything from there on belongs to transactions that are still open.
+    let consumer = create_isolated_consumer(IsolationLevel::ReadCommitted)?;
+    let mut stable_offsets: HashMap<i32, i64> = HashMap::new();
+    for range in &ranges {
+        let (_, stable_offset) = consumer
+            .fetch_watermarks(topic, range.partition, Duration::from_secs(3))
+            .map_err(|_| "Failed to fetch watermarks")?;
+        stable_offsets.insert(range.partition, stable_offset);
+    }
+
+    for message in messages.iter_mut() {
+        let key = (message.partition, message.offset);
+        let status = if message.offset >= stable_offsets[&message.partition] {
+            TransactionStatus::Open
+        } else if committed.contains(&key) {
+            TransactionStatus::Committed
+        } else {
+            TransactionStatus::Aborted
+        };
+        message.transaction_status = Some(status);
+    }
+
+    let mut markers: Vec<TransactionMarker> = ranges
+        .iter()
+        .flat_map(|range| (range.start..range.end).map(move |offset| (range.partition, offset)))
+        .filter(|key| !uncommitted.contains(key))
+        .map(|(partition, offset)| TransactionMarker { partition, offset })
+        .collect();
+    markers.sort_by_key(|marker| (marker.partition, marker.offset));
+
+    Ok(markers)
+}
+
+fn read_offsets(
+    topic: &str,
+    ranges: &[PartitionRange],
+    isolation: IsolationLevel,
+) -> Result<HashSet<(i32, i64)>, &'static str> {
+    let mut offsets = HashSet::new();
+    scan_isolated(topic, ranges, isolation, |event| {
+        if let ScanEvent::Message(m) = event {
+            offsets.insert((m.partition(), m.offset()));
+        }
+        true
+    })?;
+
+    Ok(offsets)
+}
@@ -0,0 +1,149 @@
+#[macro_use]
+extern crate serde_derive;
+
+use std::collections::HashMap;
+use std::env;
+
+pub type PartitionOffsets = HashMap<i32, i64>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Towards newer messages, starting at the given offsets.
+    #[default]
+    Forward,
+    /// Towards older messages, ending right before the given offsets.
+    Backward,
+}
+
+/// Where to continue reading: the offsets to pass back together with the
+/// direction to read in.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PageCursor {
+    pub direction: Direction,
+    pub offsets: PartitionOffsets,
+}
+
+/// How much a page of messages may hold. Requests can ask for smaller or
+/// larger pages, up to the maximums configured with `MAX_PAGE_LIMIT`,
+/// `MAX_PER_PARTITION_LIMIT` and `MAX_PAGE_BYTES`.
+#[derive(Debug, Clone, Copy)]
+pub struct PageLimits {
+    /// Messages in the whole page.
+    pub limit: usize,
+    pub per_partition_limit: usize,
+    /// Total size of the payloads in the page, before truncating them. A page
+    /// always holds at least one message.
+    pub max_bytes: usize,
+}
+
+const DEFAULT_PAGE_LIMITS: PageLimits = PageLimits {
+    limit: 100,
+    per_partition_limit: 20,
+    max_bytes: 2_000_000,
+};
+
+impl PageLimits {
+    pub fn new(
+        limit: Option<usize>,
+        per_partition_limit: Option<usize>,
+        max_bytes: Option<usize>,
+    ) -> Self {
+        // The defaults are kept within the maximums as well.
+        PageLimits::with_defaults(
+            DEFAULT_PAGE_LIMITS,
+            limit.or(Some(DEFAULT_PAGE_LIMITS.limit)),
+            per_partition_limit.or(Some(DEFAULT_PAGE_LIMITS.per_partition_limit)),
+            max_bytes.or(Some(DEFAULT_PAGE_LIMITS.max_bytes)),
+        )
+    }
+
+    /// Takes the limits that were asked for, kept within the maximums, and
+    /// `defaults` for the others.
+    pub fn with_defaults(
+        defaults: PageLimits,
+        limit: Option<usize>,
+        per_partition_limit: Option<usize>,
+        max_bytes: Option<usize>,
+    ) -> Self {
+        PageLimits {
+            limit: limit.map_or(defaults.limit, |limit| limit.min(max_page_limit()).max(1)),
+            per_partition_limit: per_partition_limit
+                .map_or(defaults.per_partition_limit, |limit| {
+                    limit.min(env_limit("MAX_PER_PARTITION_LIMIT", 500)).max(1)
+                }),
+            max_bytes: max_bytes.map_or(defaults.max_bytes, |max_bytes| {
+                max_bytes.min(env_limit("MAX_PAGE_BYTES", 10_000_000))
+            }),
+        }
+    }
+}
+
+impl Default for PageLimits {
+    fn default() -> Self {
+        PageLimits::new(None, None, None)
+    }
+}
+
+/// The most messages, or table rows, a single page can be asked for.
+pub fn max_page_limit() -> usize {
+    env_limit("MAX_PAGE_LIMIT", 1000)
+}
+
+fn env_limit(key: &str, default: usize) -> usize {
+    env::var(key)
+        .ok()
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // MAX_PAGE_LIMIT, MAX_PER_PARTITION_LIMIT and MAX_PAGE_BYTES aren't set,
+    // so their defaults are the maximums.
+
+    #[test]
+    fn keeps_requested_limits_within_the_maximums() {
+        let limits = PageLimits::new(Some(0), Some(0), Some(0));
+        assert_eq!(limits.limit, 1);
+        assert_eq!(limits.per_partition_limit, 1);
+        assert_eq!(limits.max_bytes, 0);
+
+        let limits = PageLimits::new(Some(5000), Some(5000), Some(50_000_000));
+        assert_eq!(limits.limit, 1000);
+        assert_eq!(limits.per_partition_limit, 500);
+        assert_eq!(limits.max_bytes, 10_000_000);
+
+        let limits = PageLimits::new(Some(50), Some(5), Some(1000));
+        assert_eq!(limits.limit, 50);
+        assert_eq!(limits.per_partition_limit, 5);
+        assert_eq!(limits.max_bytes, 1000);
+    }
+
+    #[test]
+    fn uses_the_defaults_for_limits_that_are_left_out() {
+        let limits = PageLimits::default();
+        assert_eq!(limits.limit, 100);
+        assert_eq!(limits.per_partition_limit, 20);
+        assert_eq!(limits.max_bytes, 2_000_000);
+    }
+
+    #[test]
+    fn keeps_unbounded_defaults() {
+        let v1_defaults = PageLimits {
+            limit: usize::MAX,
+            per_partition_limit: 10,
+            max_bytes: 2_000_000,
+        };
+
+        let limits = PageLimits::with_defaults(v1_defaults, None, None, None);
+        assert_eq!(limits.limit, usize::MAX);
+        assert_eq!(limits.per_partition_limit, 10);
+
+        let limits = PageLimits::with_defaults(v1_defaults, Some(0), Some(usize::MAX), None);
+        assert_eq!(limits.limit, 1);
+        assert_eq!(limits.per_partition_limit, 500);
+    }
+}
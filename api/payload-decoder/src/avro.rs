@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use crate::float_value;
+
+/// An Avro schema parsed from its JSON definition, with all named types
+/// (records, enums and fixed) collected so they can be referenced by name.
+pub struct Schema {
+    root: SchemaType,
+    named: HashMap<String, SchemaType>,
+}
+
+enum SchemaType {
+    Null,
+    Boolean,
+    Int,
+    Long,
+    Float,
+    Double,
+    Bytes,
+    String,
+    Array(Box<SchemaType>),
+    Map(Box<SchemaType>),
+    Union(Vec<SchemaType>),
+    Record(Vec<(String, SchemaType)>),
+    Enum(Vec<String>),
+    Fixed(usize),
+    Named(String),
+}
+
+impl Schema {
+    pub fn parse(definition: &str) -> Result<Schema, &'static str> {
+        let json: Value =
+            serde_json::from_str(definition).map_err(|_| "Avro schema is not valid JSON")?;
+        let mut named = HashMap::new();
+        let root = parse_type(&json, "", &mut named)?;
+
+        Ok(Schema { root, named })
+    }
+
+    /// Decodes a single datum in Avro binary encoding into its JSON form.
+    pub fn decode(&self, bytes: &[u8]) -> Result<Value, &'static str> {
+        let mut reader = Reader { bytes, position: 0 };
+
+        self.decode_type(&self.root, &mut reader)
+    }
+
+    fn decode_type(&self, schema: &SchemaType, reader: &mut Reader) -> Result<Value, &'static str> {
+        let value = match schema {
+            SchemaType::Null => Value::Null,
+            SchemaType::Boolean => Value::Bool(reader.read_byte()? != 0),
+            SchemaType::Int | SchemaType::Long => Value::from(reader.read_long()?),
+            SchemaType::Float => {
+                let mut buf = [0; 4];
+                buf.copy_from_slice(reader.read_bytes(4)?);
+                float_value(f64::from(f32::from_le_bytes(buf)))
+            }
+            SchemaType::Double => {
+                let mut buf = [0; 8];
+                buf.copy_from_slice(reader.read_bytes(8)?);
+                float_value(f64::from_le_bytes(buf))
+            }
+            SchemaType::Bytes => {
+                let len = reader.read_len()?;
+                Value::String(bytes_to_string(reader.read_bytes(len)?))
+            }
+            SchemaType::String => {
+                let len = reader.read_len()?;
+                let s = std::str::from_utf8(reader.read_bytes(len)?)
+                    .map_err(|_| "Avro string is not valid UTF-8")?;
+                Value::String(String::from(s))
+            }
+            SchemaType::Fixed(size) => Value::String(bytes_to_string(reader.read_bytes(*size)?)),
+            SchemaType::Enum(symbols) => {
+                let index = reader.read_long()?;
+                let symbol = symbols
+                    .get(index as usize)
+                    .ok_or("Avro enum index out of range")?;
+                Value::String(symbol.clone())
+            }
+            SchemaType::Union(variants) => {
+                let index = reader.read_long()?;
+                let variant = variants
+                    .get(index as usize)
+                    .ok_or("Avro union index out of range")?;
+                self.decode_type(variant, reader)?
+            }
+            SchemaType::Record(fields) => {
+                let mut record = Map::new();
+                for (name, field) in fields {
+                    record.insert(name.clone(), self.decode_type(field, reader)?);
+                }
+                Value::Object(record)
+            }
+            SchemaType::Array(items) => {
+                let mut array = vec![];
+                while let Some(count) = reader.read_block_count()? {
+                    for _ in 0..count {
+                        array.push(self.decode_type(items, reader)?);
+                    }
+                }
+                Value::Array(array)
+            }
+            SchemaType::Map(values) => {
+                let mut map = Map::new();
+                while let Some(count) = reader.read_block_count()? {
+                    for _ in 0..count {
+                        let len = reader.read_len()?;
+                        let key = std::str::from_utf8(reader.read_bytes(len)?)
+                            .map_err(|_| "Avro map key is not valid UTF-8")?;
+                        let key = String::from(key);
+                        map.insert(key, self.decode_type(values, reader)?);
+                    }
+                }
+                Value::Object(map)
+            }
+            SchemaType::Named(name) => {
+                let schema = self
+                    .named
+                    .get(name)
+                    .ok_or("Avro schema references an unknown type")?;
+                self.decode_type(schema, reader)?
+            }
+        };
+
+        Ok(value)
+    }
+}
+
+fn parse_type(
+    json: &Value,
+    namespace: &str,
+    named: &mut HashMap<String, SchemaType>,
+) -> Result<SchemaType, &'static str> {
+    match json {
+        Value::String(name) => Ok(parse_type_name(name, namespace)),
+        Value::Array(variants) => {
+            let variants = variants
+                .iter()
+                .map(|variant| parse_type(variant, namespace, named))
+                .collect::<Result<Vec<SchemaType>, &'static str>>()?;
+            Ok(SchemaType::Union(variants))
+        }
+        Value::Object(object) => {
+            let type_name = object.get("type").ok_or("Avro schema is missing a type")?;
+            let type_name = match type_name {
+                Value::String(type_name) => type_name.as_str(),
+                // e.g. {"type": {"type": "array", ...}} or {"type": ["null", "string"]}
+                nested => return parse_type(nested, namespace, named),
+            };
+
+            match type_name {
+                "array" => {
+                    let items = object.get("items").ok_or("Avro array is missing items")?;
+                    Ok(SchemaType::Array(Box::new(parse_type(
+                        items, namespace, named,
+                    )?)))
+                }
+                "map" => {
+                    let values = object.get("values").ok_or("Avro map is missing values")?;
+                    Ok(SchemaType::Map(Box::new(parse_type(
+                        values, namespace, named,
+                    )?)))
+                }
+                "record" | "error" | "enum" | "fixed" => {
+                    let (full_name, namespace) = full_name(object, namespace)?;
+                    let schema = match type_name {
+                        "enum" => {
+                            let symbols = object
+                                .get("symbols")
+                                .and_then(|s| s.as_array())
+                                .ok_or("Avro enum is missing symbols")?
+                                .iter()
+                                .filter_map(|s| s.as_str().map(String::from))
+                                .collect();
+                            SchemaType::Enum(symbols)
+                        }
+                        "fixed" => {
+                            let size = object
+                                .get("size")
+                                .and_then(|s| s.as_u64())
+                                .ok_or("Avro fixed is missing size")?;
+                            SchemaType::Fixed(size as usize)
+                        }
+                        _ => {
+                            // Register the name before parsing the fields so
+                            // recursive records can refer to themselves.
+                            named.insert(full_name.clone(), SchemaType::Null);
+                            let fields = object
+                                .get("fields")
+                                .and_then(|f| f.as_array())
+                                .ok_or("Avro record is missing fields")?
+                                .iter()
+                                .map(|field| {
+                                    let name = field
+                                        .get("name")
+                                        .and_then(|n| n.as_str())
+                                        .ok_or("Avro field is missing a name")?;
+                                    let schema =
+                                        field.get("type").ok_or("Avro field is missing a type")?;
+                                    Ok((String::from(name), parse_type(schema, &namespace, named)?))
+                                })
+                                .collect::<Result<Vec<(String, SchemaType)>, &'static str>>()?;
+                            SchemaType::Record(fields)
+                        }
+                    };
+                    named.insert(full_name.clone(), schema);
+
+                    Ok(SchemaType::Named(full_name))
+                }
+                primitive => Ok(parse_type_name(primitive, namespace)),
+            }
+        }
+        _ => Err("Avro schema has an invalid type"),
+    }
+}
+
+fn parse_type_name(name: &str, namespace: &str) -> SchemaType {
+    match name {
+        "null" => SchemaType::Null,
+        "boolean" => SchemaType::Boolean,
+        "int" => SchemaType::Int,
+        "long" => SchemaType::Long,
+        "float" => SchemaType::Float,
+        "double" => SchemaType::Double,
+        "bytes" => SchemaType::Bytes,
+        "string" => SchemaType::String,
+        name if name.contains('.') || namespace.is_empty() => SchemaType::Named(String::from(name)),
+        name => SchemaType::Named(format!("{}.{}", namespace, name)),
+    }
+}
+
+fn full_name(
+    object: &Map<String, Value>,
+    namespace: &str,
+) -> Result<(String, String), &'static str> {
+    let name = object
+        .get("name")
+        .and_then(|n| n.as_str())
+        .ok_or("Avro named type is missing a name")?;
+
+    if let Some(index) = name.rfind('.') {
+        return Ok((String::from(name), String::from(&name[..index])));
+    }
+
+    let namespace = object
+        .get("namespace")
+        .and_then(|n| n.as_str())
+        .unwrap_or(namespace);
+
+    if namespace.is_empty() {
+        Ok((String::from(name), String::new()))
+    } else {
+        Ok((format!("{}.{}", namespace, name), String::from(namespace)))
+    }
+}
+
+/// Bytes and fixed values are rendered the way the Avro JSON encoding does:
+/// every byte becomes the unicode code point with the same value.
+fn bytes_to_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| *b as char).collect()
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read_byte(&mut self) -> Result<u8, &'static str> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or("Unexpected end of Avro data")?;
+        self.position += 1;
+
+        Ok(byte)
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], &'static str> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or("Unexpected end of Avro data")?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+
+        Ok(bytes)
+    }
+
+    /// Reads a zigzag encoded variable length integer.
+    fn read_long(&mut self) -> Result<i64, &'static str> {
+        let mut value: u64 = 0;
+        let mut shift = 0;
+        loop {
+            if shift >= 64 {
+                return Err("Avro integer is too long");
+            }
+            let byte = self.read_byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+        }
+
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    fn read_len(&mut self) -> Result<usize, &'static str> {
+        let len = self.read_long()?;
+        if len < 0 {
+            return Err("Avro length is negative");
+        }
+
+        Ok(len as usize)
+    }
+
+    /// Arrays and maps are encoded as a series of blocks, ending with an
+    /// empty block. A negative count is followed by the block size in bytes.
+    fn read_block_count(&mut self) -> Result<Option<i64>, &'static str> {
+        let count = self.read_long()?;
+        if count == 0 {
+            return Ok(None);
+        }
+        if count < 0 {
+            self.read_long()?;
+            return Ok(Some(-count));
+        }
+
+        Ok(Some(count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn decode(schema: &str, bytes: &[u8]) -> Result<Value, &'static str> {
+        Schema::parse(schema)?.decode(bytes)
+    }
+
+    #[test]
+    fn decodes_zigzag_varints() {
+        assert_eq!(decode(r#""long""#, &[0x00]), Ok(json!(0)));
+        assert_eq!(decode(r#""long""#, &[0x01]), Ok(json!(-1)));
+        assert_eq!(decode(r#""long""#, &[0x02]), Ok(json!(1)));
+        assert_eq!(decode(r#""long""#, &[0x7f]), Ok(json!(-64)));
+        assert_eq!(decode(r#""long""#, &[0x80, 0x01]), Ok(json!(64)));
+        assert_eq!(decode(r#""int""#, &[0xac, 0x02]), Ok(json!(150)));
+        assert_eq!(
+            decode(
+                r#""long""#,
+                &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]
+            ),
+            Ok(json!(i64::MIN))
+        );
+        assert!(decode(r#""long""#, &[0xff; 11]).is_err());
+    }
+
+    #[test]
+    fn decodes_primitives() {
+        assert_eq!(decode(r#""null""#, &[]), Ok(json!(null)));
+        assert_eq!(decode(r#""boolean""#, &[0x01]), Ok(json!(true)));
+        assert_eq!(decode(r#""float""#, &1.5f32.to_le_bytes()), Ok(json!(1.5)));
+        assert_eq!(
+            decode(r#""double""#, &(-0.25f64).to_le_bytes()),
+            Ok(json!(-0.25))
+        );
+        assert_eq!(decode(r#""string""#, b"\x06abc"), Ok(json!("abc")));
+        assert_eq!(
+            decode(r#""bytes""#, &[0x04, 0x00, 0xff]),
+            Ok(json!("\u{0}\u{ff}"))
+        );
+    }
+
+    #[test]
+    fn decodes_records_with_unions() {
+        let schema = r#"{
+            "type": "record",
+            "name": "User",
+            "namespace": "example",
+            "fields": [
+                {"name": "name", "type": "string"},
+                {"name": "nickname", "type": ["null", "string"]}
+            ]
+        }"#;
+
+        assert_eq!(
+            decode(schema, b"\x06Ann\x02\x04Al"),
+            Ok(json!({"name": "Ann", "nickname": "Al"}))
+        );
+        assert_eq!(
+            decode(schema, b"\x06Ann\x00"),
+            Ok(json!({"name": "Ann", "nickname": null}))
+        );
+        assert!(decode(schema, b"\x06Ann\x04").is_err());
+    }
+
+    #[test]
+    fn decodes_arrays_and_maps_in_blocks() {
+        let array = r#"{"type": "array", "items": "int"}"#;
+        assert_eq!(
+            decode(array, &[0x06, 0x02, 0x04, 0x06, 0x00]),
+            Ok(json!([1, 2, 3]))
+        );
+        // A negative count is followed by the size of the block in bytes
+        assert_eq!(
+            decode(array, &[0x03, 0x04, 0x02, 0x04, 0x02, 0x06, 0x00]),
+            Ok(json!([1, 2, 3]))
+        );
+        assert_eq!(decode(array, &[0x00]), Ok(json!([])));
+
+        let map = r#"{"type": "map", "values": "long"}"#;
+        assert_eq!(
+            decode(map, b"\x04\x02a\x02\x02b\x04\x00"),
+            Ok(json!({"a": 1, "b": 2}))
+        );
+    }
+
+    #[test]
+    fn decodes_logical_types_as_their_underlying_type() {
+        let schema = r#"{
+            "type": "record",
+            "name": "Event",
+            "fields": [
+                {"name": "day", "type": {"type": "int", "logicalType": "date"}},
+                {"name": "at", "type": {"type": "long", "logicalType": "timestamp-millis"}},
+                {"name": "id", "type": {"type": "string", "logicalType": "uuid"}},
+                {
+                    "name": "amount",
+                    "type": {"type": "bytes", "logicalType": "decimal", "precision": 4, "scale": 2}
+                }
+            ]
+        }"#;
+
+        assert_eq!(
+            decode(schema, b"\xa0\x99\x02\x80\x01\x02x\x04\x04\xd2"),
+            Ok(json!({"day": 18000, "at": 64, "id": "x", "amount": "\u{4}\u{d2}"}))
+        );
+    }
+
+    #[test]
+    fn decodes_named_types() {
+        let schema = r#"{
+            "type": "record",
+            "name": "Node",
+            "namespace": "example",
+            "fields": [
+                {"name": "color", "type": {"type": "enum", "name": "Color", "symbols": ["RED", "BLUE"]}},
+                {"name": "hash", "type": {"type": "fixed", "name": "Hash", "size": 2}},
+                {"name": "other", "type": "Color"},
+                {"name": "next", "type": ["null", "Node"]}
+            ]
+        }"#;
+
+        assert_eq!(
+            decode(schema, b"\x02ab\x00\x02\x00cd\x02\x00"),
+            Ok(json!({
+                "color": "BLUE",
+                "hash": "ab",
+                "other": "RED",
+                "next": {"color": "RED", "hash": "cd", "other": "BLUE", "next": null}
+            }))
+        );
+        assert!(decode(schema, b"\x04ab\x00\x00").is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        assert_eq!(
+            decode(r#""string""#, b"\x06a"),
+            Err("Unexpected end of Avro data")
+        );
+        assert_eq!(
+            decode(r#""long""#, &[0x80]),
+            Err("Unexpected end of Avro data")
+        );
+        assert_eq!(
+            decode(r#""bytes""#, &[0x01]),
+            Err("Avro length is negative")
+        );
+    }
+}
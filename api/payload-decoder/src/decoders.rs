@@ -0,0 +1,378 @@
+use std::convert::TryFrom;
+
+use serde_json::Value;
+
+use crate::{
+    confluent_schema_id, float_value, get_schema_registry_url, hex_preview, protobuf,
+    schema_registry, DecodedPayload, Encoding, PayloadDecoder, SchemaInfo, CONFLUENT_HEADER_LENGTH,
+};
+
+/// Avro in the Confluent wire format, with the writer schema fetched from the
+/// schema registry.
+pub struct AvroDecoder;
+
+impl PayloadDecoder for AvroDecoder {
+    fn name(&self) -> &'static str {
+        "avro"
+    }
+
+    fn detect(&self, _topic: &str, payload: &[u8]) -> bool {
+        confluent_schema_id(payload).is_some() && get_schema_registry_url().is_some()
+    }
+
+    fn decode(&self, _topic: &str, payload: &[u8]) -> Result<DecodedPayload, &'static str> {
+        let id = confluent_schema_id(payload).ok_or("Payload has no schema id")?;
+        let registered = schema_registry::fetch_schema(id)?;
+        let value = registered
+            .schema
+            .decode(&payload[CONFLUENT_HEADER_LENGTH..])?;
+
+        Ok(DecodedPayload {
+            schema: Some(SchemaInfo {
+                id: registered.id,
+                subject: registered.subject.clone(),
+            }),
+            ..DecodedPayload::new(value.to_string())
+        })
+    }
+}
+
+/// Protobuf, plain or in the Confluent wire format, decoded as the message
+/// type configured for the topic.
+pub struct ProtobufDecoder;
+
+impl PayloadDecoder for ProtobufDecoder {
+    fn name(&self) -> &'static str {
+        "protobuf"
+    }
+
+    fn detect(&self, topic: &str, _payload: &[u8]) -> bool {
+        protobuf::message_type_for_topic(topic).is_some()
+    }
+
+    /// A valid Protobuf message never starts with a zero byte, so a leading
+    /// magic byte reliably tells framed and plain payloads apart.
+    fn decode(&self, topic: &str, payload: &[u8]) -> Result<DecodedPayload, &'static str> {
+        let message_type = protobuf::message_type_for_topic(topic)
+            .ok_or("No Protobuf message type configured for topic")?;
+        let schema_id = confluent_schema_id(payload);
+
+        let (json, message_type) = match schema_id {
+            Some(_) => protobuf::decode(message_type, &payload[CONFLUENT_HEADER_LENGTH..], true)?,
+            None => protobuf::decode(message_type, payload, false)?,
+        };
+
+        Ok(DecodedPayload {
+            schema: schema_id.map(|id| SchemaInfo { id, subject: None }),
+            message_type: Some(message_type),
+            ..DecodedPayload::new(json)
+        })
+    }
+}
+
+/// UTF-8 text that is valid JSON.
+pub struct JsonDecoder;
+
+impl PayloadDecoder for JsonDecoder {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn detect(&self, _topic: &str, payload: &[u8]) -> bool {
+        serde_json::from_slice::<serde::de::IgnoredAny>(payload).is_ok()
+    }
+
+    fn decode(&self, _topic: &str, payload: &[u8]) -> Result<DecodedPayload, &'static str> {
+        let json = std::str::from_utf8(payload).map_err(|_| "Payload is not valid UTF-8")?;
+        serde_json::from_str::<serde::de::IgnoredAny>(json)
+            .map_err(|_| "Payload is not valid JSON")?;
+
+        Ok(DecodedPayload::new(String::from(json)))
+    }
+}
+
+/// Any UTF-8 text.
+pub struct TextDecoder;
+
+impl PayloadDecoder for TextDecoder {
+    fn name(&self) -> &'static str {
+        "text"
+    }
+
+    fn detect(&self, _topic: &str, payload: &[u8]) -> bool {
+        std::str::from_utf8(payload).is_ok()
+    }
+
+    fn decode(&self, _topic: &str, payload: &[u8]) -> Result<DecodedPayload, &'static str> {
+        let text = std::str::from_utf8(payload).map_err(|_| "Payload is not valid UTF-8")?;
+
+        Ok(DecodedPayload::new(String::from(text)))
+    }
+}
+
+/// MessagePack, rendered as JSON. Binary and extension values become base64
+/// strings and map keys that aren't strings are rendered as text.
+pub struct MessagePackDecoder;
+
+impl PayloadDecoder for MessagePackDecoder {
+    fn name(&self) -> &'static str {
+        "msgpack"
+    }
+
+    /// Almost any byte sequence is valid MessagePack, so it has to be
+    /// configured for a topic explicitly.
+    fn detect(&self, _topic: &str, _payload: &[u8]) -> bool {
+        false
+    }
+
+    fn decode(&self, _topic: &str, payload: &[u8]) -> Result<DecodedPayload, &'static str> {
+        let value = rmpv::decode::read_value(&mut &payload[..])
+            .map_err(|_| "Payload is not valid MessagePack")?;
+
+        Ok(DecodedPayload::new(msgpack_to_json(value).to_string()))
+    }
+}
+
+fn msgpack_to_json(value: rmpv::Value) -> Value {
+    match value {
+        rmpv::Value::Nil => Value::Null,
+        rmpv::Value::Boolean(b) => Value::Bool(b),
+        rmpv::Value::Integer(i) => match (i.as_i64(), i.as_u64()) {
+            (Some(i), _) => Value::from(i),
+            (_, Some(u)) => Value::from(u),
+            _ => Value::Null,
+        },
+        rmpv::Value::F32(f) => float_value(f64::from(f)),
+        rmpv::Value::F64(f) => float_value(f),
+        rmpv::Value::String(s) => match s.into_str() {
+            Some(s) => Value::String(s),
+            None => Value::Null,
+        },
+        rmpv::Value::Binary(bytes) | rmpv::Value::Ext(_, bytes) => {
+            Value::String(base64::encode(&bytes))
+        }
+        rmpv::Value::Array(values) => {
+            Value::Array(values.into_iter().map(msgpack_to_json).collect())
+        }
+        rmpv::Value::Map(entries) => Value::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = match key {
+                        rmpv::Value::String(s) => s.into_str().unwrap_or_default(),
+                        key => key.to_string(),
+                    };
+                    (key, msgpack_to_json(value))
+                })
+                .collect(),
+        ),
+    }
+}
+
+/// CBOR, rendered as JSON. Byte strings become base64 strings, tags are
+/// dropped and map keys that aren't strings are rendered as JSON text.
+pub struct CborDecoder;
+
+impl PayloadDecoder for CborDecoder {
+    fn name(&self) -> &'static str {
+        "cbor"
+    }
+
+    /// CBOR can't be told apart from other binary formats reliably, so it
+    /// has to be configured for a topic explicitly.
+    fn detect(&self, _topic: &str, _payload: &[u8]) -> bool {
+        false
+    }
+
+    fn decode(&self, _topic: &str, payload: &[u8]) -> Result<DecodedPayload, &'static str> {
+        let value: serde_cbor::Value =
+            serde_cbor::from_slice(payload).map_err(|_| "Payload is not valid CBOR")?;
+
+        Ok(DecodedPayload::new(cbor_to_json(value).to_string()))
+    }
+}
+
+fn cbor_to_json(value: serde_cbor::Value) -> Value {
+    match value {
+        serde_cbor::Value::Bool(b) => Value::Bool(b),
+        serde_cbor::Value::Integer(i) => {
+            if let Ok(i) = i64::try_from(i) {
+                Value::from(i)
+            } else if let Ok(u) = u64::try_from(i) {
+                Value::from(u)
+            } else {
+                Value::String(i.to_string())
+            }
+        }
+        serde_cbor::Value::Float(f) => float_value(f),
+        serde_cbor::Value::Bytes(bytes) => Value::String(base64::encode(&bytes)),
+        serde_cbor::Value::Text(s) => Value::String(s),
+        serde_cbor::Value::Array(values) => {
+            Value::Array(values.into_iter().map(cbor_to_json).collect())
+        }
+        serde_cbor::Value::Map(entries) => Value::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = match key {
+                        serde_cbor::Value::Text(s) => s,
+                        key => cbor_to_json(key).to_string(),
+                    };
+                    (key, cbor_to_json(value))
+                })
+                .collect(),
+        ),
+        serde_cbor::Value::Tag(_, value) => cbor_to_json(*value),
+        _ => Value::Null,
+    }
+}
+
+/// Raw bytes as base64, the decoder of last resort.
+pub struct BinaryDecoder;
+
+impl PayloadDecoder for BinaryDecoder {
+    fn name(&self) -> &'static str {
+        "binary"
+    }
+
+    fn detect(&self, _topic: &str, _payload: &[u8]) -> bool {
+        true
+    }
+
+    fn decode(&self, _topic: &str, payload: &[u8]) -> Result<DecodedPayload, &'static str> {
+        Ok(DecodedPayload {
+            encoding: Encoding::Base64,
+            hex_preview: Some(hex_preview(payload)),
+            ..DecodedPayload::new(base64::encode(payload))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn decode_msgpack(value: &rmpv::Value) -> String {
+        let mut payload = vec![];
+        rmpv::encode::write_value(&mut payload, value).unwrap();
+
+        MessagePackDecoder.decode("topic", &payload).unwrap().json
+    }
+
+    fn decode_cbor(value: serde_cbor::Value) -> String {
+        let payload = serde_cbor::to_vec(&value).unwrap();
+
+        CborDecoder.decode("topic", &payload).unwrap().json
+    }
+
+    #[test]
+    fn decodes_msgpack() {
+        let value = rmpv::Value::Map(vec![
+            (rmpv::Value::from("name"), rmpv::Value::from("Ann")),
+            (
+                rmpv::Value::from("tags"),
+                rmpv::Value::Array(vec![rmpv::Value::Nil, rmpv::Value::from(true)]),
+            ),
+            (rmpv::Value::from("big"), rmpv::Value::from(u64::MAX)),
+            (rmpv::Value::from("small"), rmpv::Value::from(-3)),
+            (rmpv::Value::from("ratio"), rmpv::Value::F64(0.5)),
+            (rmpv::Value::from("nan"), rmpv::Value::F32(f32::NAN)),
+        ]);
+
+        assert_eq!(
+            decode_msgpack(&value),
+            format!(
+                r#"{{"big":{},"name":"Ann","nan":null,"ratio":0.5,"small":-3,"tags":[null,true]}}"#,
+                u64::MAX
+            )
+        );
+    }
+
+    #[test]
+    fn decodes_msgpack_binary_as_base64() {
+        let value = rmpv::Value::Array(vec![
+            rmpv::Value::Binary(vec![0, 1, 255]),
+            rmpv::Value::Ext(1, vec![104, 105]),
+        ]);
+
+        assert_eq!(decode_msgpack(&value), r#"["AAH/","aGk="]"#);
+    }
+
+    #[test]
+    fn decodes_msgpack_keys_that_are_not_strings() {
+        let value = rmpv::Value::Map(vec![
+            (rmpv::Value::from(1), rmpv::Value::from("one")),
+            (rmpv::Value::from(false), rmpv::Value::from("no")),
+        ]);
+
+        assert_eq!(decode_msgpack(&value), r#"{"1":"one","false":"no"}"#);
+    }
+
+    #[test]
+    fn rejects_invalid_msgpack() {
+        // An array of two values that ends after the first
+        assert!(MessagePackDecoder.decode("topic", &[0x92, 0x01]).is_err());
+    }
+
+    #[test]
+    fn decodes_cbor() {
+        let mut map = BTreeMap::new();
+        map.insert(
+            serde_cbor::Value::Text(String::from("name")),
+            serde_cbor::Value::Text(String::from("Ann")),
+        );
+        map.insert(
+            serde_cbor::Value::Text(String::from("tags")),
+            serde_cbor::Value::Array(vec![serde_cbor::Value::Null, serde_cbor::Value::Bool(true)]),
+        );
+        map.insert(
+            serde_cbor::Value::Text(String::from("small")),
+            serde_cbor::Value::Integer(-3),
+        );
+        map.insert(
+            serde_cbor::Value::Text(String::from("ratio")),
+            serde_cbor::Value::Float(0.5),
+        );
+        map.insert(
+            serde_cbor::Value::Text(String::from("tagged")),
+            serde_cbor::Value::Tag(1, Box::new(serde_cbor::Value::Integer(1_571_302_800))),
+        );
+
+        assert_eq!(
+            decode_cbor(serde_cbor::Value::Map(map)),
+            r#"{"name":"Ann","ratio":0.5,"small":-3,"tagged":1571302800,"tags":[null,true]}"#
+        );
+    }
+
+    #[test]
+    fn decodes_cbor_byte_strings_as_base64() {
+        let value = serde_cbor::Value::Bytes(vec![0, 1, 255]);
+
+        assert_eq!(decode_cbor(value), r#""AAH/""#);
+    }
+
+    #[test]
+    fn decodes_cbor_keys_that_are_not_strings() {
+        let mut map = BTreeMap::new();
+        map.insert(
+            serde_cbor::Value::Integer(1),
+            serde_cbor::Value::Text(String::from("one")),
+        );
+        map.insert(
+            serde_cbor::Value::Bytes(vec![0, 1, 255]),
+            serde_cbor::Value::Text(String::from("bytes")),
+        );
+
+        assert_eq!(
+            decode_cbor(serde_cbor::Value::Map(map)),
+            r#"{"\"AAH/\"":"bytes","1":"one"}"#
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_cbor() {
+        assert!(CborDecoder.decode("topic", &[0xff]).is_err());
+    }
+}
@@ -0,0 +1,232 @@
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate lazy_static;
+
+mod avro;
+mod decoders;
+mod protobuf;
+mod registry;
+mod schema_registry;
+
+use std::env;
+
+use serde_json::{Number, Value};
+
+pub use decoders::{
+    AvroDecoder, BinaryDecoder, CborDecoder, JsonDecoder, MessagePackDecoder, ProtobufDecoder,
+    TextDecoder,
+};
+pub use registry::{DecoderRegistry, TopicPattern};
+pub use schema_registry::get_schema_registry_url;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SchemaInfo {
+    pub id: u32,
+    pub subject: Option<String>,
+}
+
+/// How the `json` field of a payload should be read: as is, or as the base64
+/// encoding of bytes that aren't valid UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Text,
+    Base64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DecodedPayload {
+    pub json: String,
+    pub decoder: String,
+    /// Length of the payload in bytes, before decoding.
+    pub size: usize,
+    pub encoding: Encoding,
+    pub hex_preview: Option<String>,
+    pub schema: Option<SchemaInfo>,
+    pub message_type: Option<String>,
+    pub error: Option<String>,
+    /// Whether `json` was cut short, see [`DecodedPayload::truncate`].
+    pub truncated: bool,
+}
+
+/// Bytes rendered without any schema: UTF-8 text is returned as is, anything
+/// else as base64 together with a hex preview of the first bytes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RawBytes {
+    pub data: String,
+    pub encoding: Encoding,
+    pub hex_preview: Option<String>,
+}
+
+const HEX_PREVIEW_BYTES: usize = 64;
+
+impl RawBytes {
+    pub fn new(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => RawBytes {
+                data: String::from(text),
+                encoding: Encoding::Text,
+                hex_preview: None,
+            },
+            Err(_) => RawBytes {
+                data: base64::encode(bytes),
+                encoding: Encoding::Base64,
+                hex_preview: Some(hex_preview(bytes)),
+            },
+        }
+    }
+}
+
+impl DecodedPayload {
+    /// Renders bytes without any schema, see [`RawBytes`].
+    pub fn raw(bytes: &[u8]) -> Self {
+        let raw = RawBytes::new(bytes);
+        let decoder = match raw.encoding {
+            Encoding::Text => "text",
+            Encoding::Base64 => "binary",
+        };
+
+        DecodedPayload {
+            json: raw.data,
+            decoder: String::from(decoder),
+            size: bytes.len(),
+            encoding: raw.encoding,
+            hex_preview: raw.hex_preview,
+            schema: None,
+            message_type: None,
+            error: None,
+            truncated: false,
+        }
+    }
+
+    /// A payload that was decoded into JSON or text.
+    pub fn new(json: String) -> Self {
+        DecodedPayload {
+            size: json.len(),
+            json,
+            decoder: String::new(),
+            encoding: Encoding::Text,
+            hex_preview: None,
+            schema: None,
+            message_type: None,
+            error: None,
+            truncated: false,
+        }
+    }
+
+    /// Cuts `json` down to at most `max_bytes`, keeping it valid UTF-8. The
+    /// result is no longer valid JSON, so listings should only show it as text.
+    pub fn truncate(&mut self, max_bytes: usize) {
+        if self.json.len() <= max_bytes {
+            return;
+        }
+
+        let mut end = max_bytes;
+        while !self.json.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.json.truncate(end);
+        self.truncated = true;
+    }
+}
+
+/// The maximum size of a payload in message listings, configured with
+/// `MAX_LISTED_PAYLOAD_BYTES`. Larger payloads are truncated and have to be
+/// fetched one by one.
+pub fn get_max_listed_payload_bytes() -> usize {
+    env::var("MAX_LISTED_PAYLOAD_BYTES")
+        .ok()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(DEFAULT_MAX_LISTED_PAYLOAD_BYTES)
+}
+
+const DEFAULT_MAX_LISTED_PAYLOAD_BYTES: usize = 64 * 1024;
+
+fn hex_preview(bytes: &[u8]) -> String {
+    let preview: Vec<String> = bytes
+        .iter()
+        .take(HEX_PREVIEW_BYTES)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+
+    if bytes.len() > HEX_PREVIEW_BYTES {
+        format!("{} ...", preview.join(" "))
+    } else {
+        preview.join(" ")
+    }
+}
+
+/// A float as a JSON number, or null for NaN and infinity which JSON can't
+/// represent.
+pub(crate) fn float_value(f: f64) -> Value {
+    Number::from_f64(f)
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
+
+/// Turns the bytes of a message payload into something that can be shown.
+///
+/// Decoders are picked per topic through a [`DecoderRegistry`], either by a
+/// configured mapping or by asking every decoder whether it recognises the
+/// payload.
+pub trait PayloadDecoder: Send + Sync {
+    /// The name used to refer to this decoder in configuration, and reported
+    /// back with every payload it decoded.
+    fn name(&self) -> &'static str;
+
+    /// Whether this decoder recognises the payload, used when no decoder is
+    /// configured for the topic.
+    fn detect(&self, topic: &str, payload: &[u8]) -> bool;
+
+    fn decode(&self, topic: &str, payload: &[u8]) -> Result<DecodedPayload, &'static str>;
+}
+
+const CONFLUENT_MAGIC_BYTE: u8 = 0;
+const CONFLUENT_HEADER_LENGTH: usize = 5;
+
+/// Returns the schema id of a payload in the Confluent wire format: a zero
+/// magic byte followed by a big endian schema id and the encoded data.
+pub fn confluent_schema_id(payload: &[u8]) -> Option<u32> {
+    if payload.len() < CONFLUENT_HEADER_LENGTH || payload[0] != CONFLUENT_MAGIC_BYTE {
+        return None;
+    }
+
+    let mut id = [0; 4];
+    id.copy_from_slice(&payload[1..CONFLUENT_HEADER_LENGTH]);
+
+    Some(u32::from_be_bytes(id))
+}
+
+lazy_static! {
+    static ref DECODER_REGISTRY: DecoderRegistry = DecoderRegistry::from_env();
+}
+
+/// Decodes a message payload for display with the decoder configured for the
+/// topic, see [`DecoderRegistry::decode`].
+pub fn decode_payload(topic: &str, payload: &[u8]) -> DecodedPayload {
+    DECODER_REGISTRY.decode(topic, payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_confluent_schema_ids() {
+        assert_eq!(confluent_schema_id(&[0, 0, 0, 1, 0x2c, 0x02]), Some(300));
+        assert_eq!(confluent_schema_id(&[0, 0, 0, 0, 7]), Some(7));
+        assert_eq!(
+            confluent_schema_id(&[0, 0xff, 0xff, 0xff, 0xff]),
+            Some(u32::MAX)
+        );
+    }
+
+    #[test]
+    fn ignores_payloads_without_confluent_framing() {
+        assert_eq!(confluent_schema_id(&[1, 0, 0, 0, 7, 0]), None);
+        assert_eq!(confluent_schema_id(&[0, 0, 0, 7]), None);
+        assert_eq!(confluent_schema_id(b"{\"a\": 1}"), None);
+        assert_eq!(confluent_schema_id(&[]), None);
+    }
+}
@@ -0,0 +1,223 @@
+use std::env;
+use std::fs;
+
+use prost::encoding::decode_varint;
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor};
+
+use crate::registry::{topic_mappings_from_env, TopicPattern};
+
+/// Descriptor sets and the topic to message type mapping used to decode
+/// Protobuf payloads.
+///
+/// `PROTOBUF_DESCRIPTOR_SETS` is a comma separated list of files compiled with
+/// `protoc --include_imports --descriptor_set_out`. `PROTOBUF_TOPIC_TYPES` is a
+/// comma separated list of `topic=message.Type` pairs, where the topic is a
+/// name or a regex.
+struct ProtobufConfig {
+    pool: DescriptorPool,
+    topics: Vec<(TopicPattern, String)>,
+}
+
+lazy_static! {
+    static ref PROTOBUF_CONFIG: ProtobufConfig = ProtobufConfig::from_env();
+}
+
+impl ProtobufConfig {
+    fn from_env() -> Self {
+        let mut pool = DescriptorPool::new();
+        let descriptor_sets = env::var("PROTOBUF_DESCRIPTOR_SETS").unwrap_or_default();
+
+        for path in descriptor_sets.split(',').filter(|p| !p.is_empty()) {
+            let loaded = fs::read(path).map_err(|e| e.to_string()).and_then(|bytes| {
+                pool.decode_file_descriptor_set(bytes.as_slice())
+                    .map_err(|e| e.to_string())
+            });
+            if let Err(e) = loaded {
+                eprintln!("Error loading descriptor set {}: {}", path, e);
+            }
+        }
+
+        let topics = topic_mappings_from_env("PROTOBUF_TOPIC_TYPES");
+
+        ProtobufConfig { pool, topics }
+    }
+
+    fn message_type(&self, topic: &str) -> Option<&str> {
+        self.topics
+            .iter()
+            .find(|(pattern, _)| pattern.matches(topic))
+            .map(|(_, message_type)| message_type.as_str())
+    }
+}
+
+/// Returns the message type configured for a topic, if any.
+pub fn message_type_for_topic(topic: &str) -> Option<&'static str> {
+    PROTOBUF_CONFIG.message_type(topic)
+}
+
+/// Decodes a Protobuf payload into canonical proto3 JSON, returning the
+/// JSON and the full name of the message type that was used.
+///
+/// Payloads in the Confluent wire format carry a list of message indexes
+/// after the schema id, pointing at a message in the file that contains the
+/// configured type. Plain payloads are decoded as the configured type itself.
+pub fn decode(
+    message_type: &str,
+    payload: &[u8],
+    confluent_framed: bool,
+) -> Result<(String, String), &'static str> {
+    let configured = PROTOBUF_CONFIG
+        .pool
+        .get_message_by_name(message_type)
+        .ok_or("Protobuf message type not found in descriptor sets")?;
+
+    decode_message(configured, payload, confluent_framed)
+}
+
+fn decode_message(
+    configured: MessageDescriptor,
+    payload: &[u8],
+    confluent_framed: bool,
+) -> Result<(String, String), &'static str> {
+    let mut data = payload;
+    let descriptor = if confluent_framed {
+        let indexes = read_message_indexes(&mut data)?;
+        resolve_message_indexes(&configured, &indexes)?
+    } else {
+        configured
+    };
+
+    let message =
+        DynamicMessage::decode(descriptor.clone(), data).map_err(|_| "Invalid Protobuf payload")?;
+    let json = serde_json::to_string(&message).map_err(|_| "Can't render Protobuf as JSON")?;
+
+    Ok((json, String::from(descriptor.full_name())))
+}
+
+fn read_zigzag_varint(data: &mut &[u8]) -> Result<i64, &'static str> {
+    let value = decode_varint(data).map_err(|_| "Invalid Protobuf message indexes")?;
+
+    Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+}
+
+/// A single zero stands for the first message in the file, otherwise the
+/// indexes are prefixed with their count.
+fn read_message_indexes(data: &mut &[u8]) -> Result<Vec<usize>, &'static str> {
+    let count = read_zigzag_varint(data)?;
+    if count == 0 {
+        return Ok(vec![0]);
+    }
+
+    (0..count)
+        .map(|_| {
+            let index = read_zigzag_varint(data)?;
+            if index < 0 {
+                return Err("Invalid Protobuf message indexes");
+            }
+            Ok(index as usize)
+        })
+        .collect()
+}
+
+fn resolve_message_indexes(
+    configured: &MessageDescriptor,
+    indexes: &[usize],
+) -> Result<MessageDescriptor, &'static str> {
+    let file = configured.parent_file();
+    let mut indexes = indexes.iter();
+
+    let first = indexes.next().ok_or("Invalid Protobuf message indexes")?;
+    let mut descriptor = file
+        .messages()
+        .nth(*first)
+        .ok_or("Protobuf message index not found in descriptor")?;
+
+    for index in indexes {
+        // Map entries are generated types that don't count towards the indexes
+        let child = descriptor
+            .child_messages()
+            .filter(|child| !child.is_map_entry())
+            .nth(*index);
+        descriptor = child.ok_or("Protobuf message index not found in descriptor")?;
+    }
+
+    Ok(descriptor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compiled from `testdata/shop.proto`.
+    const SHOP_DESCRIPTOR_SET: &[u8] = include_bytes!("../testdata/shop.desc");
+
+    fn decode_shop(
+        message_type: &str,
+        payload: &[u8],
+        confluent_framed: bool,
+    ) -> Result<(String, String), &'static str> {
+        let pool = DescriptorPool::decode(SHOP_DESCRIPTOR_SET).unwrap();
+        let configured = pool.get_message_by_name(message_type).unwrap();
+
+        decode_message(configured, payload, confluent_framed)
+    }
+
+    // An Order with id "o1" and amount_cents 150
+    const ORDER: &[u8] = b"\x0a\x02o1\x10\x96\x01";
+
+    #[test]
+    fn decodes_plain_payloads_as_the_configured_type() {
+        assert_eq!(
+            decode_shop("shop.Order", ORDER, false),
+            Ok((
+                String::from(r#"{"id":"o1","amountCents":"150"}"#),
+                String::from("shop.Order")
+            ))
+        );
+    }
+
+    #[test]
+    fn reads_a_single_zero_as_the_first_message() {
+        let payload = [&[0x00], ORDER].concat();
+
+        assert_eq!(
+            decode_shop("shop.Customer", &payload, true),
+            Ok((
+                String::from(r#"{"id":"o1","amountCents":"150"}"#),
+                String::from("shop.Order")
+            ))
+        );
+    }
+
+    #[test]
+    fn resolves_message_indexes() {
+        // [1], the Customer
+        assert_eq!(
+            decode_shop("shop.Order", b"\x02\x02\x0a\x03Ann", true),
+            Ok((
+                String::from(r#"{"name":"Ann"}"#),
+                String::from("shop.Customer")
+            ))
+        );
+        // [0, 0], Order.Line as the generated LabelsEntry doesn't count
+        assert_eq!(
+            decode_shop("shop.Order", b"\x04\x00\x00\x0a\x01a\x10\x02", true),
+            Ok((
+                String::from(r#"{"sku":"a","quantity":2}"#),
+                String::from("shop.Order.Line")
+            ))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_message_indexes() {
+        assert_eq!(
+            decode_shop("shop.Order", b"\x02\x04", true),
+            Err("Protobuf message index not found in descriptor")
+        );
+        assert_eq!(
+            decode_shop("shop.Order", b"\x02\x01", true),
+            Err("Invalid Protobuf message indexes")
+        );
+    }
+}
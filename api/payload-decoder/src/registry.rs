@@ -0,0 +1,156 @@
+use std::env;
+
+use regex::Regex;
+
+use crate::decoders::{
+    AvroDecoder, BinaryDecoder, CborDecoder, JsonDecoder, MessagePackDecoder, ProtobufDecoder,
+    TextDecoder,
+};
+use crate::{DecodedPayload, PayloadDecoder};
+
+/// Matches a topic either by its exact name or by a regex that has to match
+/// the whole topic name.
+pub struct TopicPattern {
+    name: String,
+    regex: Option<Regex>,
+}
+
+impl TopicPattern {
+    pub fn new(pattern: &str) -> Self {
+        let regex = match Regex::new(&format!("^(?:{})$", pattern)) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                eprintln!("Topic pattern {} is not a valid regex: {}", pattern, e);
+                None
+            }
+        };
+
+        TopicPattern {
+            name: String::from(pattern),
+            regex,
+        }
+    }
+
+    pub fn matches(&self, topic: &str) -> bool {
+        self.name == topic || self.regex.as_ref().is_some_and(|r| r.is_match(topic))
+    }
+}
+
+/// Parses a comma separated list of `topic=value` pairs from an environment
+/// variable, where the topic is a [`TopicPattern`].
+pub fn topic_mappings_from_env(key: &str) -> Vec<(TopicPattern, String)> {
+    env::var(key)
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|mapping| {
+            let mut parts = mapping.splitn(2, '=');
+            let topic = parts.next()?.trim();
+            let value = parts.next()?.trim();
+
+            Some((TopicPattern::new(topic), String::from(value)))
+        })
+        .collect()
+}
+
+/// The decoders that are available and which of them to use for which topic.
+pub struct DecoderRegistry {
+    decoders: Vec<Box<dyn PayloadDecoder>>,
+    topics: Vec<(TopicPattern, String)>,
+}
+
+impl Default for DecoderRegistry {
+    /// All built-in decoders, in the order they're tried when detecting.
+    fn default() -> Self {
+        DecoderRegistry {
+            decoders: vec![
+                Box::new(ProtobufDecoder),
+                Box::new(AvroDecoder),
+                Box::new(JsonDecoder),
+                Box::new(TextDecoder),
+                Box::new(MessagePackDecoder),
+                Box::new(CborDecoder),
+                Box::new(BinaryDecoder),
+            ],
+            topics: vec![],
+        }
+    }
+}
+
+impl DecoderRegistry {
+    /// The built-in decoders with the topic mapping from `PAYLOAD_DECODERS`,
+    /// a comma separated list of `topic=decoder` pairs.
+    pub fn from_env() -> Self {
+        let mut registry = DecoderRegistry::default();
+
+        for (topic, decoder) in topic_mappings_from_env("PAYLOAD_DECODERS") {
+            if registry.get(&decoder).is_none() {
+                eprintln!(
+                    "Unknown payload decoder {} for topic {}",
+                    decoder, topic.name
+                );
+                continue;
+            }
+            registry.topics.push((topic, decoder));
+        }
+
+        registry
+    }
+
+    /// Adds a decoder, which takes precedence over the built-in ones when
+    /// detecting.
+    pub fn register(&mut self, decoder: Box<dyn PayloadDecoder>) {
+        self.decoders.insert(0, decoder);
+    }
+
+    /// Uses the named decoder for all topics matching the pattern.
+    pub fn map_topic(&mut self, pattern: &str, decoder: &str) {
+        self.topics
+            .push((TopicPattern::new(pattern), String::from(decoder)));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn PayloadDecoder> {
+        self.decoders
+            .iter()
+            .find(|d| d.name() == name)
+            .map(|d| d.as_ref())
+    }
+
+    /// The configured decoder for the topic, or the first one that recognises
+    /// the payload.
+    pub fn decoder_for(&self, topic: &str, payload: &[u8]) -> &dyn PayloadDecoder {
+        let configured = self
+            .topics
+            .iter()
+            .find(|(pattern, _)| pattern.matches(topic))
+            .and_then(|(_, name)| self.get(name));
+
+        configured
+            .or_else(|| {
+                self.decoders
+                    .iter()
+                    .find(|d| d.detect(topic, payload))
+                    .map(|d| d.as_ref())
+            })
+            .unwrap_or(&BinaryDecoder)
+    }
+
+    /// Decodes a message payload for display. This never fails: when a
+    /// payload can't be decoded, the raw bytes are returned along with the
+    /// reason.
+    pub fn decode(&self, topic: &str, payload: &[u8]) -> DecodedPayload {
+        let decoder = self.decoder_for(topic, payload);
+
+        match decoder.decode(topic, payload) {
+            Ok(decoded) => DecodedPayload {
+                decoder: String::from(decoder.name()),
+                size: payload.len(),
+                ..decoded
+            },
+            Err(e) => DecodedPayload {
+                error: Some(String::from(e)),
+                size: payload.len(),
+                ..DecodedPayload::raw(payload)
+            },
+        }
+    }
+}
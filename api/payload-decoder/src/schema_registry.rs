@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::avro::Schema;
+
+/// A writer schema as registered in the schema registry.
+pub struct RegisteredSchema {
+    pub id: u32,
+    pub subject: Option<String>,
+    pub schema: Schema,
+}
+
+#[derive(Debug, Deserialize)]
+struct SchemaByIdResponse {
+    schema: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubjectVersionResponse {
+    subject: String,
+}
+
+/// How long a single request to the registry may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+/// How long a failed lookup is remembered, so an unreachable registry isn't
+/// asked again for every message of a page.
+const FAILED_LOOKUP_TTL: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    static ref SCHEMA_CACHE: Mutex<HashMap<u32, Arc<RegisteredSchema>>> =
+        Mutex::new(HashMap::new());
+    static ref FAILED_LOOKUPS: Mutex<HashMap<u32, (Instant, &'static str)>> =
+        Mutex::new(HashMap::new());
+    static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new());
+}
+
+pub fn get_schema_registry_url() -> Option<String> {
+    env::var("SCHEMA_REGISTRY_URL")
+        .ok()
+        .map(|url| String::from(url.trim_end_matches('/')))
+}
+
+/// Looks up a schema by id, only asking the registry when it's not cached
+/// yet. Schemas are immutable once registered so they never need refreshing,
+/// failed lookups are retried after a while.
+pub fn fetch_schema(id: u32) -> Result<Arc<RegisteredSchema>, &'static str> {
+    if let Some(schema) = SCHEMA_CACHE
+        .lock()
+        .map_err(|_| "Schema cache is poisoned")?
+        .get(&id)
+    {
+        return Ok(schema.clone());
+    }
+
+    let mut failed_lookups = FAILED_LOOKUPS
+        .lock()
+        .map_err(|_| "Schema cache is poisoned")?;
+    if let Some((failed_at, e)) = failed_lookups.get(&id) {
+        if failed_at.elapsed() < FAILED_LOOKUP_TTL {
+            return Err(e);
+        }
+    }
+    failed_lookups.remove(&id);
+    drop(failed_lookups);
+
+    let schema = request_schema(id);
+    match &schema {
+        Ok(schema) => {
+            SCHEMA_CACHE
+                .lock()
+                .map_err(|_| "Schema cache is poisoned")?
+                .insert(id, schema.clone());
+        }
+        Err(e) => {
+            FAILED_LOOKUPS
+                .lock()
+                .map_err(|_| "Schema cache is poisoned")?
+                .insert(id, (Instant::now(), e));
+        }
+    }
+
+    schema
+}
+
+fn request_schema(id: u32) -> Result<Arc<RegisteredSchema>, &'static str> {
+    let url = get_schema_registry_url().ok_or("No schema registry configured")?;
+
+    let response: SchemaByIdResponse = CLIENT
+        .get(&format!("{}/schemas/ids/{}", url, id))
+        .send()
+        .and_then(|res| res.error_for_status()?.json())
+        .map_err(|_| "Error fetching schema from schema registry")?;
+
+    // Older registries don't support this lookup, the subject is optional.
+    let subject = CLIENT
+        .get(&format!("{}/schemas/ids/{}/versions", url, id))
+        .send()
+        .and_then(|res| {
+            res.error_for_status()?
+                .json::<Vec<SubjectVersionResponse>>()
+        })
+        .ok()
+        .and_then(|versions| versions.into_iter().next())
+        .map(|version| version.subject);
+
+    Ok(Arc::new(RegisteredSchema {
+        id,
+        subject,
+        schema: Schema::parse(&response.schema)?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // These rely on SCHEMA_REGISTRY_URL not being set, so a lookup that gets
+    // through to the registry fails with "No schema registry configured".
+
+    #[test]
+    fn remembers_failed_lookups() {
+        FAILED_LOOKUPS
+            .lock()
+            .unwrap()
+            .insert(1001, (Instant::now(), "Registry unreachable"));
+
+        assert_eq!(fetch_schema(1001).err(), Some("Registry unreachable"));
+    }
+
+    #[test]
+    fn retries_failed_lookups_after_ttl() {
+        let failed_at = Instant::now() - FAILED_LOOKUP_TTL - Duration::from_secs(1);
+        FAILED_LOOKUPS
+            .lock()
+            .unwrap()
+            .insert(1002, (failed_at, "Registry unreachable"));
+
+        assert_eq!(
+            fetch_schema(1002).err(),
+            Some("No schema registry configured")
+        );
+        let (retried_at, _) = FAILED_LOOKUPS.lock().unwrap()[&1002];
+        assert!(retried_at > failed_at);
+        assert_eq!(
+            fetch_schema(1002).err(),
+            Some("No schema registry configured")
+        );
+    }
+}
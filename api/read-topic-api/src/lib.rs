@@ -1,14 +1,14 @@
 #[macro_use]
 extern crate serde_derive;
 
-use kafka::client::{fetch, FetchOffset, FetchPartition, PartitionOffset, ProduceMessage};
+use kafka::client::{fetch, FetchOffset, FetchPartition, PartitionOffset};
 use std::collections::HashMap;
 
 pub use kafka::client::KafkaClient;
-use kafka::producer::RequiredAcks;
+pub use paging::{Direction, PageCursor, PageLimits, PartitionOffsets};
+use payload_decoder::{decode_payload, get_max_listed_payload_bytes, DecodedPayload, RawBytes};
 use std::cmp::max;
 use std::env;
-use std::time::Duration;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PartitionResponse {
@@ -31,13 +31,16 @@ impl TopicResponse {
     }
 }
 
-pub type PartitionOffsets = HashMap<i32, i64>;
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TopicDetailResponse {
     name: String,
+    /// The lowest offset read per partition.
     partition_offsets: PartitionOffsets,
     partition_details: Vec<PartitionDetailResponse>,
+    /// Continues in the direction that was read.
+    next: PageCursor,
+    /// Goes back the other way, to the messages before this page.
+    previous: PageCursor,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -50,7 +53,9 @@ pub struct PartitionDetailResponse {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MessageResponse {
-    json: String,
+    key: Option<RawBytes>,
+    #[serde(flatten)]
+    payload: DecodedPayload,
     offset: i64,
     partition: i32,
 }
@@ -78,27 +83,61 @@ pub fn fetch_topics(client: &mut KafkaClient) -> Result<Vec<TopicResponse>, &'st
     Ok(topics)
 }
 
+/// The v1 api has always read 10 messages per partition, and only limits
+/// the page as a whole when asked to, so every partition gets its share.
+const DEFAULT_PAGE_LIMITS: PageLimits = PageLimits {
+    limit: usize::MAX,
+    per_partition_limit: 10,
+    max_bytes: 2_000_000,
+};
+
+pub fn page_limits(
+    limit: Option<usize>,
+    per_partition_limit: Option<usize>,
+    max_bytes: Option<usize>,
+) -> PageLimits {
+    PageLimits::with_defaults(DEFAULT_PAGE_LIMITS, limit, per_partition_limit, max_bytes)
+}
+
+/// What Kafka returns per fetch, split over the partitions. The page's
+/// `max_bytes` is only applied to the messages that come back, as messages
+/// that don't fit in a fetch are dropped.
 const MAX_BYTES_MESSAGES: i32 = 2_000_000;
 
+/// Reads a page of messages, starting at the given offsets when reading
+/// forward, or ending right before them when reading backward. The page
+/// stops at the first message that doesn't fit in the limits, so the cursors
+/// never skip a message.
 pub fn fetch_from_topic_detail(
     client: &mut KafkaClient,
     topic_name: &str,
     from: &PartitionOffsets,
+    direction: Direction,
+    limits: &PageLimits,
 ) -> Result<TopicDetailResponse, &'static str> {
     let reqs: Vec<FetchPartition> = from
         .iter()
         .map(|(partition, offset)| {
-            FetchPartition::new(topic_name, *partition, max(offset - 10, 0))
+            let start = match direction {
+                Direction::Forward => *offset,
+                Direction::Backward => max(offset - limits.per_partition_limit as i64, 0),
+            };
+            FetchPartition::new(topic_name, *partition, start)
                 .with_max_bytes(MAX_BYTES_MESSAGES / from.len() as i32)
         })
         .collect();
     client.load_metadata_all();
 
+    let max_payload_bytes = get_max_listed_payload_bytes();
     let response: Vec<fetch::Response> = client
         .fetch_messages(reqs)
         .map_err(|_| "Error fetching messages")?;
     let partition_details: Vec<PartitionDetailResponse> = vec![];
     let mut partition_offsets = HashMap::new();
+    let mut highest_offsets: PartitionOffsets = HashMap::new();
+    let mut page_messages = 0;
+    let mut page_bytes = 0;
+    let mut page_full = false;
     let partition_details: Vec<PartitionDetailResponse> =
         response
             .iter()
@@ -117,12 +156,44 @@ pub fn fetch_from_topic_detail(
                                 &Ok(ref data) => {
                                     highwatermark_offset = data.highwatermark_offset();
 
-                                    data.messages().iter().fold(
+                                    // Reading backward keeps the messages closest to
+                                    // the starting point when the page fills up.
+                                    let ordered: Vec<&fetch::Message> = match direction {
+                                        Direction::Forward => data.messages().iter().collect(),
+                                        Direction::Backward => {
+                                            data.messages().iter().rev().collect()
+                                        }
+                                    };
+
+                                    ordered.into_iter().fold(
                                         messages,
                                         |mut acc, message: &fetch::Message| {
-                                            if acc.len() == 10 {
+                                            if page_full || acc.len() >= limits.per_partition_limit
+                                            {
+                                                return acc;
+                                            }
+
+                                            let in_page = match from.get(&partition.partition()) {
+                                                None => true,
+                                                Some(from) => match direction {
+                                                    Direction::Forward => message.offset >= *from,
+                                                    Direction::Backward => message.offset < *from,
+                                                },
+                                            };
+                                            if !in_page {
+                                                return acc;
+                                            }
+
+                                            if page_messages >= limits.limit
+                                                || (page_messages > 0
+                                                    && page_bytes + message.value.len()
+                                                        > limits.max_bytes)
+                                            {
+                                                page_full = true;
                                                 return acc;
                                             }
+                                            page_messages += 1;
+                                            page_bytes += message.value.len();
 
                                             // Store lowest offset
                                             let offset = partition_offsets
@@ -131,12 +202,28 @@ pub fn fetch_from_topic_detail(
                                             if message.offset < *offset {
                                                 *offset = message.offset;
                                             }
+                                            let highest = highest_offsets
+                                                .entry(partition.partition())
+                                                .or_insert(message.offset);
+                                            if message.offset > *highest {
+                                                *highest = message.offset;
+                                            }
+
+                                            let mut payload =
+                                                decode_payload(topic_name, message.value);
+                                            payload.truncate(max_payload_bytes);
+
+                                            // The kafka client doesn't tell null and empty keys
+                                            // apart, both are reported as null.
+                                            let key = if message.key.is_empty() {
+                                                None
+                                            } else {
+                                                Some(RawBytes::new(message.key))
+                                            };
 
                                             let message_response = MessageResponse {
-                                                json: String::from(
-                                                    std::str::from_utf8(message.value)
-                                                        .expect("Failed to read string"),
-                                                ),
+                                                key,
+                                                payload,
                                                 offset: message.offset,
                                                 partition: partition.partition(),
                                             };
@@ -148,6 +235,9 @@ pub fn fetch_from_topic_detail(
                                 }
                                 _ => messages,
                             };
+                            if direction == Direction::Backward {
+                                messages.reverse();
+                            }
 
                             acc.push(PartitionDetailResponse {
                                 id: partition.partition() as u32,
@@ -161,37 +251,37 @@ pub fn fetch_from_topic_detail(
                 })
             });
 
+    // Partitions without messages on this page stay where they were
+    let next = from
+        .iter()
+        .map(|(p, offset)| {
+            let next = match direction {
+                Direction::Forward => highest_offsets.get(p).map_or(*offset, |o| o + 1),
+                Direction::Backward => *partition_offsets.get(p).unwrap_or(offset),
+            };
+            (*p, next)
+        })
+        .collect();
+    let reversed = match direction {
+        Direction::Forward => Direction::Backward,
+        Direction::Backward => Direction::Forward,
+    };
+
     Ok(TopicDetailResponse {
         name: String::from(topic_name),
         partition_offsets,
         partition_details,
+        next: PageCursor {
+            direction,
+            offsets: next,
+        },
+        previous: PageCursor {
+            direction: reversed,
+            offsets: from.clone(),
+        },
     })
 }
 
-pub fn send_message_to_topic(
-    client: &mut KafkaClient,
-    topic_name: &str,
-    partition: i32,
-    message: &str,
-) -> Result<(), &'static str> {
-    client
-        .load_metadata_all()
-        .map_err(|_| "Error loading metadata")?;
-
-    let messages = vec![ProduceMessage::new(
-        topic_name,
-        partition,
-        None,
-        Some(message.as_bytes()),
-    )];
-
-    let resp = client.produce_messages(RequiredAcks::One, Duration::from_millis(100), messages);
-
-    resp.map_err(|_| "Error producing messag")?;
-
-    Ok(())
-}
-
 pub fn fetch_latest_topic_detail(
     client: &mut KafkaClient,
     topic_name: &str,
@@ -209,5 +299,11 @@ pub fn fetch_latest_topic_detail(
         acc
     });
 
-    fetch_from_topic_detail(client, topic_name, &from)
+    fetch_from_topic_detail(
+        client,
+        topic_name,
+        &from,
+        Direction::Backward,
+        &page_limits(None, None, None),
+    )
 }
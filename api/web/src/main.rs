@@ -4,10 +4,16 @@ extern crate serde_derive;
 #[macro_use]
 extern crate actix_web;
 
+mod tail_ws;
+
 use std::collections::HashMap;
-use std::{env, io};
+use std::{env, io, thread};
 
-use futures::Future;
+use bytes::Bytes;
+use futures::future::{self, Either};
+use futures::sync::mpsc;
+use futures::{Future, Sink, Stream};
+use regex::Regex;
 
 use actix_cors::Cors;
 use actix_files as fs;
@@ -15,10 +21,15 @@ use actix_web::http::StatusCode;
 use actix_web::web::Query;
 use actix_web::{error, guard, middleware, web, App, Error, HttpResponse, HttpServer, Result};
 
-use kafka_admin::{consume, delete_topic, fetch_topic_detail, reset_topic};
+use kafka_admin::{
+    consume, consume_from_timestamp, decode_table_key, delete_topic, fetch_message,
+    fetch_topic_detail, latest_offsets, materialize_table, produce, produce_batch, reset_topic,
+    search, search_key, tail, Acks, Cursor, Direction, Filter, IsolationLevel, PageLimits,
+    Partitioner, ProduceError, ProduceErrorKind, ProduceOptions, ProduceRecord, ReadOptions,
+    SearchEvent, SearchPattern, SearchRequest, TableRequest, TailEvent,
+};
 use read_topic_api::{
     fetch_from_topic_detail, fetch_latest_topic_detail, fetch_topics, get_client,
-    send_message_to_topic,
 };
 
 #[get("/favicon")]
@@ -42,51 +53,300 @@ fn fetch_topics_handler() -> impl Future<Item = HttpResponse, Error = Error> {
     })
 }
 
+#[derive(Deserialize, Debug)]
+struct MessagesQuery {
+    /// Continues where an earlier page left off, replacing `offsets`,
+    /// `from_timestamp`, `direction`, `filter` and `isolation`.
+    cursor: Option<String>,
+    offsets: Option<String>,
+    from_timestamp: Option<i64>,
+    direction: Option<Direction>,
+    filter: Option<String>,
+    isolation: Option<IsolationLevel>,
+    limit: Option<usize>,
+    per_partition_limit: Option<usize>,
+    max_bytes: Option<usize>,
+}
+
 fn fetch_messages(
     topic_name: web::Path<String>,
-    offsets: Query<Offsets>,
+    query: Query<MessagesQuery>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let cursor = match query
+        .cursor
+        .as_ref()
+        .map(|c| Cursor::decode(c, &topic_name))
+        .transpose()
+    {
+        Ok(cursor) => cursor,
+        Err(e) => return Either::A(future::ok(HttpResponse::BadRequest().body(e))),
+    };
+    let filter_expression = match &cursor {
+        Some(cursor) => cursor.filter().map(String::from),
+        None => query.filter.clone(),
+    };
+    let filter = match filter_expression
+        .as_ref()
+        .map(|f| Filter::parse(f))
+        .transpose()
+    {
+        Ok(filter) => filter,
+        Err(e) => return Either::A(future::ok(HttpResponse::BadRequest().body(e))),
+    };
+    let direction = match &cursor {
+        Some(cursor) => cursor.direction(),
+        None => query.direction.unwrap_or(Direction::Forward),
+    };
+    let isolation = match &cursor {
+        Some(cursor) => cursor.isolation(),
+        None => query.isolation.unwrap_or_default(),
+    };
+    let options = ReadOptions {
+        direction,
+        page_limits: PageLimits::new(query.limit, query.per_partition_limit, query.max_bytes),
+        isolation,
+    };
+    if cursor.is_none()
+        && query.offsets.is_none()
+        && query.from_timestamp.is_none()
+        && direction == Direction::Forward
+    {
+        return Either::A(future::ok(
+            HttpResponse::BadRequest().body("Either cursor, offsets or from_timestamp is required"),
+        ));
+    }
+
+    Either::B(
+        web::block(move || {
+            let response = match (cursor, query.from_timestamp, &query.offsets) {
+                (None, Some(timestamp), _) => {
+                    consume_from_timestamp(&topic_name, timestamp, &options, filter.as_ref())
+                }
+                (cursor, _, offsets) => {
+                    // Reading backward without offsets starts at the latest messages
+                    let offsets = match (cursor, offsets) {
+                        (Some(cursor), _) => cursor.offsets_for_topic()?,
+                        (None, Some(offsets)) => parse_offsets(offsets),
+                        (None, None) => latest_offsets(&topic_name)?,
+                    };
+                    consume(&topic_name, &offsets, &options, filter.as_ref())
+                }
+            };
+
+            response
+                .map(|response| response.with_cursors(&topic_name, filter_expression.as_deref()))
+        })
+        .then(|res| match res {
+            Ok(topics) => Ok(HttpResponse::Ok().json(topics)),
+            Err(_) => Ok(HttpResponse::InternalServerError().into()),
+        }),
+    )
+}
+
+/// Parses offsets in the form `partition;offset,partition;offset`.
+fn parse_offsets(offsets: &str) -> HashMap<i32, i64> {
+    let partitions: Vec<(i32, i64)> = offsets
+        .split(',')
+        .map(|el| {
+            let els: Vec<&str> = el.split(';').collect();
+            let partition = els.first().expect("No partition found in offsets");
+            let offset = els.last().expect("No offset found in offsets");
+
+            return (
+                partition
+                    .parse::<i32>()
+                    .expect("Offsets should contain numbers"),
+                offset
+                    .parse::<i64>()
+                    .expect("Offsets should contain numbers"),
+            );
+        })
+        .collect();
+
+    partitions
+        .iter()
+        .fold(HashMap::new(), |mut acc, (partition, offset)| {
+            acc.insert(*partition, *offset);
+            acc
+        })
+}
+
+#[derive(Deserialize, Debug)]
+struct FetchMessageQuery {
+    isolation: Option<IsolationLevel>,
+}
+
+fn fetch_message_handler(
+    path: web::Path<(String, i32, i64)>,
+    query: Query<FetchMessageQuery>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let isolation = query.isolation.unwrap_or_default();
+
+    web::block(move || fetch_message(&path.0, path.1, path.2, isolation)).then(|res| match res {
+        Ok(Ok(message)) => Ok(HttpResponse::Ok().json(message)),
+        Ok(Err(not_found)) => Ok(HttpResponse::NotFound().json(not_found)),
+        Err(_) => Ok(HttpResponse::InternalServerError().into()),
+    })
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchQuery {
+    query: String,
+    regex: Option<bool>,
+    from_offset: Option<i64>,
+    to_offset: Option<i64>,
+    from_timestamp: Option<i64>,
+    to_timestamp: Option<i64>,
+}
+
+/// Streams search results as newline delimited JSON while the topic is
+/// being scanned, the search stops when the client disconnects.
+fn search_handler(topic_name: web::Path<String>, query: Query<SearchQuery>) -> HttpResponse {
+    let query = query.into_inner();
+    let pattern = if query.regex.unwrap_or(false) {
+        match Regex::new(&query.query) {
+            Ok(regex) => SearchPattern::Regex(regex),
+            Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+        }
+    } else {
+        SearchPattern::Substring(query.query)
+    };
+
+    let request = SearchRequest {
+        pattern,
+        from_offset: query.from_offset,
+        to_offset: query.to_offset,
+        from_timestamp: query.from_timestamp,
+        to_timestamp: query.to_timestamp,
+    };
+
+    let topic_name = topic_name.into_inner();
+    let (sender, receiver) = mpsc::channel::<Bytes>(100);
+    thread::spawn(move || {
+        let mut sink = sender.wait();
+        let mut send = |event: &SearchEvent| {
+            let mut line = serde_json::to_vec(event).unwrap_or_default();
+            line.push(b'\n');
+            sink.send(Bytes::from(line)).is_ok()
+        };
+
+        if let Err(e) = search(&topic_name, &request, |event| send(&event)) {
+            send(&SearchEvent::Error {
+                error: String::from(e),
+            });
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(receiver.map_err(|_| error::ErrorInternalServerError("Search failed")))
+}
+
+#[derive(Deserialize, Debug)]
+struct TailQuery {
+    filter: Option<String>,
+}
+
+/// Streams new messages as server-sent events until the client disconnects.
+fn tail_handler(topic_name: web::Path<String>, query: Query<TailQuery>) -> HttpResponse {
+    let filter = match query.filter.as_ref().map(|f| Filter::parse(f)).transpose() {
+        Ok(filter) => filter,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
+    let topic_name = topic_name.into_inner();
+    let (sender, receiver) = mpsc::channel::<Bytes>(100);
+    thread::spawn(move || {
+        let mut sink = sender.wait();
+        let result = tail(&topic_name, filter, |event| {
+            let event = match event {
+                TailEvent::Message { message, .. } => format!(
+                    "event: message\ndata: {}\n\n",
+                    serde_json::to_string(&message).unwrap_or_default()
+                ),
+                TailEvent::Heartbeat => String::from(": heartbeat\n\n"),
+                TailEvent::Error(e) => format!("event: error\ndata: {}\n\n", e),
+            };
+            sink.send(Bytes::from(event)).is_ok()
+        });
+
+        if let Err(e) = result {
+            let _ = sink.send(Bytes::from(format!("event: error\ndata: {}\n\n", e)));
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .streaming(receiver.map_err(|_| error::ErrorInternalServerError("Tail failed")))
+}
+
+#[derive(Deserialize, Debug)]
+struct KeySearchQuery {
+    key: String,
+    all_partitions: Option<bool>,
+}
+
+fn search_key_handler(
+    topic_name: web::Path<String>,
+    query: Query<KeySearchQuery>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
     web::block(move || {
-        let partitions: Vec<(i32, i64)> = offsets
-            .offsets
-            .split(',')
-            .map(|el| {
-                let els: Vec<&str> = el.split(';').collect();
-                let partition = els.first().expect("No partition found in offsets");
-                let offset = els.last().expect("No offset found in offsets");
-
-                return (
-                    partition
-                        .parse::<i32>()
-                        .expect("Offsets should contain numbers"),
-                    offset
-                        .parse::<i64>()
-                        .expect("Offsets should contain numbers"),
-                );
-            })
-            .collect();
-
-        let partition_offsets: HashMap<i32, i64> =
-            partitions
-                .iter()
-                .fold(HashMap::new(), |mut acc, (partition, offset)| {
-                    acc.insert(*partition, *offset);
-                    acc
-                });
-
-        consume(
-            "localhost:9092",
-            "hello_123",
+        search_key(
             &topic_name,
-            &partition_offsets,
+            query.key.as_bytes(),
+            query.all_partitions.unwrap_or(false),
         )
     })
     .then(|res| match res {
-        Ok(topics) => Ok(HttpResponse::Ok().json(topics)),
+        Ok(messages) => Ok(HttpResponse::Ok().json(messages)),
         Err(_) => Ok(HttpResponse::InternalServerError().into()),
     })
 }
 
+#[derive(Deserialize, Debug)]
+struct TableQuery {
+    from_offset: Option<i64>,
+    as_of_offset: Option<i64>,
+    as_of_timestamp: Option<i64>,
+    include_tombstones: Option<bool>,
+    after_key: Option<String>,
+    limit: Option<usize>,
+}
+
+const TABLE_ROWS_PER_PAGE: usize = 100;
+
+fn fetch_table_handler(
+    topic_name: web::Path<String>,
+    query: Query<TableQuery>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let query = query.into_inner();
+    let after_key = match query
+        .after_key
+        .as_ref()
+        .map(|k| decode_table_key(k))
+        .transpose()
+    {
+        Ok(after_key) => after_key,
+        Err(e) => return Either::A(future::ok(HttpResponse::BadRequest().body(e))),
+    };
+    let request = TableRequest {
+        from_offset: query.from_offset,
+        as_of_offset: query.as_of_offset,
+        as_of_timestamp: query.as_of_timestamp,
+        include_tombstones: query.include_tombstones.unwrap_or(true),
+        after_key,
+        limit: query.limit.unwrap_or(TABLE_ROWS_PER_PAGE),
+    };
+
+    Either::B(
+        web::block(move || materialize_table(&topic_name, &request)).then(|res| match res {
+            Ok(table) => Ok(HttpResponse::Ok().json(table)),
+            Err(_) => Ok(HttpResponse::InternalServerError().into()),
+        }),
+    )
+}
+
 fn delete_topic_handler(
     topic_name: web::Path<String>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
@@ -121,16 +381,16 @@ fn fetch_topic_handler_v2(
     })
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct SendMessageRequest {
-    partition: i32,
-    message: String,
-}
-
 fn fetch_topic_detail_from_handler(
     topic_name: web::Path<String>,
     offsets: Query<Offsets>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
+    let direction = offsets.direction.unwrap_or(Direction::Backward);
+    let page_limits = read_topic_api::page_limits(
+        offsets.limit,
+        offsets.per_partition_limit,
+        offsets.max_bytes,
+    );
     // TODO: can actix-web parse lists from query?
     let partitions: Vec<(i32, i64)> = offsets
         .offsets
@@ -162,7 +422,13 @@ fn fetch_topic_detail_from_handler(
     web::block(move || {
         let mut client = get_client();
 
-        fetch_from_topic_detail(&mut client, &topic_name, &partition_offsets)
+        fetch_from_topic_detail(
+            &mut client,
+            &topic_name,
+            &partition_offsets,
+            direction,
+            &page_limits,
+        )
     })
     .then(|res| match res {
         Ok(topics) => Ok(HttpResponse::Ok().json(topics)),
@@ -173,6 +439,10 @@ fn fetch_topic_detail_from_handler(
 #[derive(Deserialize, Debug)]
 struct Offsets {
     offsets: String,
+    direction: Option<Direction>,
+    limit: Option<usize>,
+    per_partition_limit: Option<usize>,
+    max_bytes: Option<usize>,
 }
 
 fn fetch_topic_detail_handler(
@@ -189,21 +459,77 @@ fn fetch_topic_detail_handler(
     })
 }
 
+#[derive(Deserialize, Debug)]
+struct ProduceQuery {
+    acks: Option<Acks>,
+    timeout_ms: Option<u64>,
+    partitioner: Option<Partitioner>,
+}
+
 fn send_message_to_topic_handler(
     topic_name: web::Path<String>,
-    item: web::Json<SendMessageRequest>,
+    item: web::Json<ProduceRecord>,
+    query: Query<ProduceQuery>,
 ) -> impl Future<Item = HttpResponse, Error = Error> {
-    web::block(move || {
-        let mut client = get_client();
+    let options = ProduceOptions::new(query.acks, query.timeout_ms, query.partitioner);
 
-        send_message_to_topic(&mut client, &topic_name, item.partition, &item.message)
-    })
-    .then(|res| match res {
-        Ok(_) => Ok(HttpResponse::Ok().finish()),
+    web::block(move || produce(&topic_name, &item, &options)).then(|res| match res {
+        Ok(Ok(produced)) => Ok(HttpResponse::Ok().json(produced)),
+        Ok(Err(failed)) => Ok(produce_error_response(&failed)),
         Err(_) => Ok(HttpResponse::InternalServerError().into()),
     })
 }
 
+/// Parses a JSON array of records, or one record per line (NDJSON).
+fn parse_records(body: &[u8]) -> Result<Vec<ProduceRecord>, ProduceError> {
+    let body = std::str::from_utf8(body)
+        .map_err(|_| ProduceError::invalid_record(String::from("Body isn't valid UTF-8")))?;
+    if body.trim_start().starts_with('[') {
+        return serde_json::from_str(body)
+            .map_err(|e| ProduceError::invalid_record(format!("Invalid records: {}", e)));
+    }
+
+    body.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                ProduceError::invalid_record(format!("Invalid record on line {}: {}", i + 1, e))
+            })
+        })
+        .collect()
+}
+
+fn produce_batch_handler(
+    topic_name: web::Path<String>,
+    body: Bytes,
+    query: Query<ProduceQuery>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let records = match parse_records(&body) {
+        Ok(records) => records,
+        Err(e) => return Either::A(future::ok(produce_error_response(&e))),
+    };
+    let options = ProduceOptions::new(query.acks, query.timeout_ms, query.partitioner);
+
+    Either::B(
+        web::block(move || produce_batch(&topic_name, &records, &options)).then(|res| match res {
+            Ok(batch) => Ok(HttpResponse::Ok().json(batch)),
+            Err(_) => Ok(HttpResponse::InternalServerError().into()),
+        }),
+    )
+}
+
+fn produce_error_response(failed: &ProduceError) -> HttpResponse {
+    let mut response = match failed.kind() {
+        ProduceErrorKind::Timeout => HttpResponse::GatewayTimeout(),
+        ProduceErrorKind::UnknownPartition => HttpResponse::NotFound(),
+        ProduceErrorKind::InvalidRecord => HttpResponse::BadRequest(),
+        ProduceErrorKind::Other => HttpResponse::BadGateway(),
+    };
+
+    response.json(failed)
+}
+
 fn main() -> io::Result<()> {
     let port = env::var("API_PORT").unwrap_or(String::from("8080"));
 
@@ -246,21 +572,49 @@ fn main() -> io::Result<()> {
                 web::resource("api/v2/topic/{topic_name}/messages")
                     .route(web::get().to_async(fetch_messages)),
             )
+            .service(
+                web::resource("api/v2/topic/{topic_name}/search")
+                    .route(web::get().to(search_handler)),
+            )
+            .service(
+                web::resource("api/v2/topic/{topic_name}/tail").route(web::get().to(tail_handler)),
+            )
+            .service(web::resource("api/v2/tail/ws").route(web::get().to(tail_ws::tail_ws_handler)))
+            .service(
+                web::resource("api/v2/topic/{topic_name}/key")
+                    .route(web::get().to_async(search_key_handler)),
+            )
+            .service(
+                web::resource("api/v2/topic/{topic_name}/table")
+                    .route(web::get().to_async(fetch_table_handler)),
+            )
+            .service(
+                web::resource("api/v2/topic/{topic_name}/partition/{partition}/offset/{offset}")
+                    .route(web::get().to_async(fetch_message_handler)),
+            )
             .service(
                 web::resource("api/topic/{topic_name}/sendMessage")
                     .data(
                         web::JsonConfig::default()
                             .limit(10 * 1024 * 1024)
                             .error_handler(|err, _| {
-                                error::InternalError::from_response(
-                                    err,
-                                    HttpResponse::BadRequest().finish(),
-                                )
-                                .into()
+                                // Tells e.g. that the value is missing, it has to
+                                // be `null` to produce a tombstone.
+                                let failed = ProduceError::invalid_record(format!(
+                                    "Invalid record: {}",
+                                    err
+                                ));
+                                let response = produce_error_response(&failed);
+                                error::InternalError::from_response(err, response).into()
                             }),
                     )
                     .route(web::post().to_async(send_message_to_topic_handler)),
             )
+            .service(
+                web::resource("api/v2/topic/{topic_name}/messages/batch")
+                    .data(web::PayloadConfig::new(50 * 1024 * 1024))
+                    .route(web::post().to_async(produce_batch_handler)),
+            )
             // static files
             .service(fs::Files::new("/", "static").index_file("static/index.html"))
             // default
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+use actix::{Actor, ActorContext, AsyncContext, StreamHandler};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use futures::sync::mpsc;
+
+use kafka_admin::{tail_topics, Filter, MessageResponse, SeekPosition, TailCommand, TailEvent};
+
+/// Messages that are waiting for a slow client. Once full, new messages are
+/// dropped and the client is told how many before the next one it gets.
+const OUTBOX_SIZE: usize = 100;
+
+/// Commands the client sends as JSON text frames, e.g.
+/// `{"type": "subscribe", "topic": "orders", "filter": "$.amount > 100"}` or
+/// `{"type": "seek", "topic": "orders", "to": {"timestamp": 1571302800000}}`.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientCommand {
+    Subscribe {
+        topic: String,
+        filter: Option<String>,
+    },
+    Unsubscribe {
+        topic: String,
+    },
+    SetFilter {
+        topic: String,
+        filter: Option<String>,
+    },
+    Pause {
+        topic: Option<String>,
+    },
+    Resume {
+        topic: Option<String>,
+    },
+    Seek {
+        topic: String,
+        partition: Option<i32>,
+        to: SeekPosition,
+    },
+}
+
+impl ClientCommand {
+    fn into_tail_command(self) -> Result<TailCommand, String> {
+        let command = match self {
+            ClientCommand::Subscribe { topic, filter } => TailCommand::Subscribe {
+                topic,
+                filter: parse_filter(filter)?,
+            },
+            ClientCommand::Unsubscribe { topic } => TailCommand::Unsubscribe { topic },
+            ClientCommand::SetFilter { topic, filter } => TailCommand::SetFilter {
+                topic,
+                filter: parse_filter(filter)?,
+            },
+            ClientCommand::Pause { topic } => TailCommand::Pause { topic },
+            ClientCommand::Resume { topic } => TailCommand::Resume { topic },
+            ClientCommand::Seek {
+                topic,
+                partition,
+                to,
+            } => TailCommand::Seek {
+                topic,
+                partition,
+                to,
+            },
+        };
+
+        Ok(command)
+    }
+}
+
+fn parse_filter(filter: Option<String>) -> Result<Option<Filter>, String> {
+    filter.as_ref().map(|f| Filter::parse(f)).transpose()
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    Message {
+        topic: String,
+        message: MessageResponse,
+    },
+    /// Messages that didn't fit in the outbox since the last notice.
+    Dropped {
+        topic: String,
+        count: u64,
+    },
+    Heartbeat,
+    Error {
+        error: String,
+    },
+}
+
+/// A single WebSocket connection, with a thread tailing the topics it
+/// subscribed to.
+struct TailSession {
+    commands: Sender<TailCommand>,
+    tail_commands: Option<Receiver<TailCommand>>,
+}
+
+impl Actor for TailSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let commands = match self.tail_commands.take() {
+            Some(commands) => commands,
+            None => return,
+        };
+        let (sender, receiver) = mpsc::channel::<ServerMessage>(OUTBOX_SIZE);
+
+        thread::spawn(move || {
+            let mut outbox = Outbox {
+                sender,
+                dropped: HashMap::new(),
+            };
+            let result = tail_topics(commands, |event| match event {
+                TailEvent::Message { topic, message } => outbox.send_message(topic, message),
+                TailEvent::Heartbeat => {
+                    outbox.send_dropped() && outbox.send(ServerMessage::Heartbeat).is_some()
+                }
+                TailEvent::Error(e) => outbox
+                    .send(ServerMessage::Error {
+                        error: String::from(e),
+                    })
+                    .is_some(),
+            });
+
+            if let Err(e) = result {
+                outbox.send(ServerMessage::Error {
+                    error: String::from(e),
+                });
+            }
+        });
+
+        // The socket is closed once the tail thread stops.
+        ctx.add_stream(receiver);
+    }
+}
+
+impl StreamHandler<ServerMessage, ()> for TailSession {
+    fn handle(&mut self, message: ServerMessage, ctx: &mut Self::Context) {
+        ctx.text(serde_json::to_string(&message).unwrap_or_default());
+    }
+}
+
+impl StreamHandler<ws::Message, ws::ProtocolError> for TailSession {
+    fn handle(&mut self, message: ws::Message, ctx: &mut Self::Context) {
+        match message {
+            ws::Message::Ping(message) => ctx.pong(&message),
+            ws::Message::Text(text) => {
+                let command = serde_json::from_str::<ClientCommand>(&text)
+                    .map_err(|e| format!("Invalid command: {}", e))
+                    .and_then(ClientCommand::into_tail_command);
+
+                match command {
+                    Ok(command) => {
+                        if self.commands.send(command).is_err() {
+                            ctx.stop();
+                        }
+                    }
+                    Err(error) => {
+                        let message = ServerMessage::Error { error };
+                        ctx.text(serde_json::to_string(&message).unwrap_or_default());
+                    }
+                }
+            }
+            ws::Message::Close(_) => ctx.stop(),
+            _ => (),
+        }
+    }
+}
+
+/// Hands messages to the session without ever blocking the tail thread.
+struct Outbox {
+    sender: mpsc::Sender<ServerMessage>,
+    dropped: HashMap<String, u64>,
+}
+
+impl Outbox {
+    /// Returns whether the message was sent, or `None` when the session is
+    /// gone.
+    fn send(&mut self, message: ServerMessage) -> Option<bool> {
+        match self.sender.try_send(message) {
+            Ok(()) => Some(true),
+            Err(ref e) if e.is_full() => Some(false),
+            Err(_) => None,
+        }
+    }
+
+    /// Returns false when the session is gone.
+    fn send_message(&mut self, topic: String, message: MessageResponse) -> bool {
+        if !self.send_dropped() {
+            return false;
+        }
+
+        let sent = self.send(ServerMessage::Message {
+            topic: topic.clone(),
+            message,
+        });
+        if sent == Some(false) {
+            *self.dropped.entry(topic).or_insert(0) += 1;
+        }
+
+        sent.is_some()
+    }
+
+    /// Tells the client about the messages that were dropped, as far as the
+    /// outbox has room. Returns false when the session is gone.
+    fn send_dropped(&mut self) -> bool {
+        let dropped: Vec<(String, u64)> = self.dropped.drain().collect();
+        for (topic, count) in dropped {
+            match self.send(ServerMessage::Dropped {
+                topic: topic.clone(),
+                count,
+            }) {
+                Some(true) => {}
+                Some(false) => {
+                    self.dropped.insert(topic, count);
+                }
+                None => return false,
+            }
+        }
+
+        true
+    }
+}
+
+/// Tails topics over a WebSocket. Clients subscribe to topics and change
+/// their filters, pause, resume and seek by sending commands, without
+/// reconnecting.
+pub fn tail_ws_handler(req: HttpRequest, stream: web::Payload) -> Result<HttpResponse, Error> {
+    let (commands, tail_commands) = channel();
+    let session = TailSession {
+        commands,
+        tail_commands: Some(tail_commands),
+    };
+
+    ws::start(session, &req, stream)
+}